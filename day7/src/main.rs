@@ -1,501 +1,514 @@
+mod network;
+mod scheduler;
+mod search;
+
+use intcode::{parse_mem_file, Base, Limits, TraceFormat};
+use network::{run_threaded, NetworkRun, Topology};
+use scheduler::{run_cooperative, Policy, Recorder, Replay, RoundRobin, Schedule, Seeded};
+use search::{format_phases, parse_phase_set, search, Wiring};
 use std::env;
 use std::fs;
-use std::io;
-use std::sync::mpsc::{channel, Receiver, RecvError, SendError, Sender};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::thread;
-
-type Base = i64;
+use std::time::Duration;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 7 {
+    let (args, limits) = take_limits(env::args().collect());
+    let (args, trace) = take_trace(args);
+    let (args, search_options) = take_search(args);
+    let (args, network) = take_network(args);
+    let (args, scheduler) = take_scheduler(args);
+    if let Some(options) = search_options {
+        if args.len() < 2 {
+            panic!("Must supply intcode file.");
+        }
+        run_search(&args[1], &options, limits);
+        return;
+    }
+    if let Some(config) = network {
+        run_network(&config, &scheduler, limits, trace.as_ref());
+        return;
+    }
+    if args.len() < 3 {
         panic!("Must supply intcode file and phase values.");
     }
 
     let filename = &args[1];
     let input = fs::read_to_string(filename).expect("Failed to read file.");
+    let phases: Vec<Base> = args[2..]
+        .iter()
+        .map(|phase| phase.parse().expect("Invalid phase value."))
+        .collect();
+
+    let topology = Topology::amplifiers(&parse_mem_file(&input), &phases, Wiring::Feedback);
+    let run = scheduler.run(&topology, limits, trace.as_ref());
+    report_errors(&run);
+    println!("{}", run.signal().expect("Failed to receive final output"));
+}
 
-    let (tx_a, rx_b) = channel();
-    let (tx_b, rx_c) = channel();
-    let (tx_c, rx_d) = channel();
-    let (tx_d, rx_e) = channel();
-    let (tx_e, rx_a) = channel();
-
-    // Initialization values
-    let phase_a = args[2].parse::<Base>().unwrap();
-    let phase_b = args[3].parse::<Base>().unwrap();
-    let phase_c = args[4].parse::<Base>().unwrap();
-    let phase_d = args[5].parse::<Base>().unwrap();
-    let phase_e = args[6].parse::<Base>().unwrap();
-    tx_a.send(phase_a).unwrap();
-    tx_b.send(phase_b).unwrap();
-    tx_c.send(phase_c).unwrap();
-    tx_d.send(phase_d).unwrap();
-    tx_e.send(phase_e).unwrap();
-
-    tx_a.send(0).unwrap();
-
-    let mut cmp_a = Computer {
-        in_buf: rx_a,
-        out_buf: tx_a,
-        mem: parse_mem_file(&input),
-        pc: 0,
-        halted: false,
-        name: "A",
-    };
-
-    let t_a = thread::spawn(move || {
-        while !cmp_a.halted {
-            cmp_a.step();
-        }
+/// Runs the network described by the topology file at `path`, then prints
+/// the outputs of each machine.
+fn run_network(path: &str, scheduler: &Scheduling, limits: Limits, trace: Option<&TraceOptions>) {
+    let text = fs::read_to_string(path).expect("Failed to read file.");
+    let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let topology = Topology::parse(&text, |program| {
+        let program = dir.join(program);
+        fs::read_to_string(&program)
+            .map(|text| parse_mem_file(&text))
+            .map_err(|err| format!("failed to read `{}`: {}", program.display(), err))
     });
-
-    let mut cmp_b = Computer {
-        in_buf: rx_b,
-        out_buf: tx_b,
-        mem: parse_mem_file(&input),
-        pc: 0,
-        halted: false,
-        name: "B",
-    };
-
-    let t_b = thread::spawn(move || {
-        while !cmp_b.halted {
-            cmp_b.step();
+    let topology = match topology {
+        Ok(topology) => topology,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
         }
-
-        let end_val = cmp_b.in_buf.recv().expect("Failed to receive final output");
-        println!("{}", end_val);
-    });
-
-    let mut cmp_c = Computer {
-        in_buf: rx_c,
-        out_buf: tx_c,
-        mem: parse_mem_file(&input),
-        pc: 0,
-        halted: false,
-        name: "C",
     };
 
-    let t_c = thread::spawn(move || {
-        while !cmp_c.halted {
-            cmp_c.step();
-        }
-    });
-
-    let mut cmp_d = Computer {
-        in_buf: rx_d,
-        out_buf: tx_d,
-        mem: parse_mem_file(&input),
-        pc: 0,
-        halted: false,
-        name: "D",
-    };
+    let run = scheduler.run(&topology, limits, trace);
+    report_errors(&run);
+    for result in &run.results {
+        let outputs: Vec<String> = result.outputs.iter().map(|v| v.to_string()).collect();
+        println!("{}: {}", result.name, outputs.join(","));
+    }
+}
 
-    let t_d = thread::spawn(move || {
-        while !cmp_d.halted {
-            cmp_d.step();
+/// Prints each machine's error, then exits if the machines deadlocked.
+fn report_errors(run: &NetworkRun) {
+    for result in &run.results {
+        if let Some(err) = &result.error {
+            println!("[{}] {}", result.name, err);
         }
-    });
-
-    let mut cmp_e = Computer {
-        in_buf: rx_e,
-        out_buf: tx_e,
-        mem: parse_mem_file(&input),
-        pc: 0,
-        halted: false,
-        name: "E",
-    };
+    }
+    if let Some(deadlock) = &run.deadlock {
+        eprintln!("{}", deadlock);
+        process::exit(1);
+    }
+}
 
-    let t_e = thread::spawn(move || {
-        while !cmp_e.halted {
-            cmp_e.step();
+/// Removes the optional `--max-instructions N` and `--timeout-ms N` flags from
+/// `args`, returning the remaining arguments and the limits they set.
+fn take_limits(args: Vec<String>) -> (Vec<String>, Limits) {
+    let mut rest = Vec::new();
+    let mut limits = Limits::none();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-instructions" => {
+                let value = args.next().expect("Missing value for --max-instructions.");
+                limits.max_instructions = Some(
+                    value
+                        .parse()
+                        .expect("Invalid value for --max-instructions."),
+                );
+            }
+            "--timeout-ms" => {
+                let value = args.next().expect("Missing value for --timeout-ms.");
+                let millis = value.parse().expect("Invalid value for --timeout-ms.");
+                limits.max_duration = Some(Duration::from_millis(millis));
+            }
+            _ => rest.push(arg),
         }
-    });
-
-    t_a.join().unwrap();
-    t_b.join().unwrap();
-    t_c.join().unwrap();
-    t_d.join().unwrap();
-    t_e.join().unwrap();
+    }
+    (rest, limits)
 }
 
-fn parse_mem_file(input: &str) -> Vec<Base> {
-    // Split the input on commas
-    input
-        .split(",")
-        .map(|s| s.trim())
-        .map(|s| s.parse().expect("Not a number."))
-        .collect()
+/// Removes the optional `--network FILE` flag from `args`, returning the
+/// remaining arguments and the topology file it names.
+fn take_network(args: Vec<String>) -> (Vec<String>, Option<String>) {
+    let mut rest = Vec::new();
+    let mut network = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--network" => {
+                network = Some(args.next().expect("Missing value for --network."));
+            }
+            _ => rest.push(arg),
+        }
+    }
+    (rest, network)
 }
 
-struct Computer {
-    in_buf: Receiver<Base>,
-    out_buf: Sender<Base>,
-    mem: Vec<Base>,
-    pc: usize,
-    halted: bool,
-    name: &'static str,
+/// How `--scheduler`, `--slice`, `--seed` and `--replay-schedule` ask for a
+/// network to be run.
+#[derive(Clone, Debug, PartialEq)]
+enum Scheduler {
+    /// Each machine on its own thread.
+    Threads,
+    /// Every machine on the main thread, taking turns of `slice`
+    /// instructions.
+    RoundRobin { slice: u64 },
+    /// Every machine on the main thread, in an order and for turns of up to
+    /// `slice` instructions chosen by a generator seeded with `seed`.
+    Seeded { seed: u64, slice: u64 },
+    /// Every machine on the main thread, taking the turns recorded in the
+    /// schedule file at `path`.
+    Replay { path: PathBuf, slice: u64 },
 }
 
-impl Computer {
-    fn step(&mut self) {
-        if self.halted {
-            panic!("Attempted to step halted computer.");
-        }
-
-        let pc = self.pc;
-        let cmd = parse_instruction(self.mem[pc]).expect("Invalid OpCode.");
-
-        match cmd {
-            OpCode::Add { p_modes } => {
-                op_add(&mut self.mem, p_modes, pc);
-                self.pc += 4;
-            }
-            OpCode::Multiply { p_modes } => {
-                op_mult(&mut self.mem, p_modes, pc);
-                self.pc += 4;
-            }
-            OpCode::Input => {
-                self.op_input(pc);
-                self.pc += 2;
-            }
-            OpCode::Output { p_modes } => {
-                self.op_output(p_modes, pc);
-                self.pc += 2;
-            }
-            OpCode::JmpT { p_modes } => {
-                self.op_jump_t(p_modes);
-            }
-            OpCode::JmpF { p_modes } => {
-                self.op_jump_f(p_modes);
-            }
-            OpCode::Less { p_modes } => {
-                self.op_less(p_modes);
-            }
-            OpCode::Eq { p_modes } => {
-                self.op_eq(p_modes);
-            }
-            OpCode::Halt => {
-                self.halted = true;
-            }
-        }
-    }
+/// The scheduler to run with, and where `--record-schedule` asks for the
+/// turns it takes to be written.
+#[derive(Clone, Debug, PartialEq)]
+struct Scheduling {
+    scheduler: Scheduler,
+    record: Option<PathBuf>,
+}
 
-    fn op_input(&mut self, pc: usize) {
-        let out = self.mem[pc + 1] as usize;
-        let val = match self.in_buf.recv() {
-            Ok(v) => v,
-            Err(err) => {
-                println!("[{}] SendError: {:?}", self.name, err);
-                panic!(err);
+impl Scheduling {
+    fn run(&self, topology: &Topology, limits: Limits, trace: Option<&TraceOptions>) -> NetworkRun {
+        let (mut policy, slice): (Box<dyn Policy>, u64) = match &self.scheduler {
+            Scheduler::Threads => return run_threaded(topology, limits, trace),
+            Scheduler::RoundRobin { slice } => (Box::new(RoundRobin::default()), *slice),
+            Scheduler::Seeded { seed, slice } => (Box::new(Seeded::new(*seed)), *slice),
+            Scheduler::Replay { path, slice } => {
+                let text = fs::read_to_string(path).expect("Failed to read schedule.");
+                let schedule = match Schedule::parse(&text) {
+                    Ok(schedule) => schedule,
+                    Err(err) => {
+                        eprintln!("{}: {}", path.display(), err);
+                        process::exit(1);
+                    }
+                };
+                let mut replay = Replay::new(schedule);
+                let run = self.run_with(topology, limits, trace, &mut replay, *slice);
+                if let Some(turn) = replay.diverged() {
+                    eprintln!(
+                        "{}: the run stopped following the schedule at turn {}",
+                        path.display(),
+                        turn + 1
+                    );
+                }
+                return run;
             }
         };
-        self.mem[out] = val;
+        self.run_with(topology, limits, trace, policy.as_mut(), slice)
     }
 
-    fn op_output(&mut self, p_modes: [PMode; 1], pc: usize) {
-        let a = get(&self.mem, p_modes[0], pc + 1);
-        match self.out_buf.send(a) {
-            Ok(()) => {}
-            Err(SendError(err)) => println!("[{}] SendError: {:?}", self.name, err),
-        }
+    /// Runs the machines cooperatively with `policy`, recording its turns if
+    /// asked to.
+    fn run_with(
+        &self,
+        topology: &Topology,
+        limits: Limits,
+        trace: Option<&TraceOptions>,
+        policy: &mut dyn Policy,
+        slice: u64,
+    ) -> NetworkRun {
+        let path = match &self.record {
+            Some(path) => path,
+            None => return run_cooperative(topology, limits, trace, policy, slice),
+        };
+        let mut recorder = Recorder::new(policy);
+        let run = run_cooperative(topology, limits, trace, &mut recorder, slice);
+        fs::write(path, recorder.schedule.to_text()).expect("Failed to write schedule.");
+        run
     }
+}
 
-    fn op_jump_t(&mut self, p_modes: [PMode; 2]) {
-        let cmp = get(&self.mem, p_modes[0], self.pc + 1);
-        let nxt = get(&self.mem, p_modes[1], self.pc + 2) as usize;
-        if cmp != 0 {
-            self.pc = nxt;
-        } else {
-            self.pc += 3;
+/// Removes the optional `--scheduler threads|round-robin|seeded`, `--slice N`,
+/// `--seed N`, `--record-schedule FILE` and `--replay-schedule FILE` flags
+/// from `args`, returning the remaining arguments and the scheduling they
+/// choose. Machines run on threads by default, and slices default to 1000
+/// instructions. `--seed` on its own picks the seeded scheduler, which can't
+/// run without one, and is rejected with any other.
+fn take_scheduler(args: Vec<String>) -> (Vec<String>, Scheduling) {
+    let mut rest = Vec::new();
+    let mut name = None;
+    let mut slice = 1000;
+    let mut seed = None;
+    let mut record = None;
+    let mut replay = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scheduler" => name = Some(args.next().expect("Missing value for --scheduler.")),
+            "--slice" => {
+                let value = args.next().expect("Missing value for --slice.");
+                slice = value.parse().expect("Invalid value for --slice.");
+            }
+            "--seed" => {
+                let value = args.next().expect("Missing value for --seed.");
+                seed = Some(value.parse().expect("Invalid value for --seed."));
+            }
+            "--record-schedule" => {
+                let value = args.next().expect("Missing value for --record-schedule.");
+                record = Some(PathBuf::from(value));
+            }
+            "--replay-schedule" => {
+                let value = args.next().expect("Missing value for --replay-schedule.");
+                replay = Some(PathBuf::from(value));
+            }
+            _ => rest.push(arg),
         }
     }
-
-    fn op_jump_f(&mut self, p_modes: [PMode; 2]) {
-        let cmp = get(&self.mem, p_modes[0], self.pc + 1);
-        let nxt = get(&self.mem, p_modes[1], self.pc + 2) as usize;
-        if cmp == 0 {
-            self.pc = nxt;
-        } else {
-            self.pc += 3;
+    let scheduler = match (name.as_deref(), replay, seed) {
+        (Some(_), Some(_), _) => panic!("Can't combine --replay-schedule with --scheduler."),
+        (_, Some(_), Some(_)) => panic!("Can't combine --replay-schedule with --seed."),
+        (None, Some(path), None) => Scheduler::Replay { path, slice },
+        (None, None, Some(seed)) | (Some("seeded"), None, Some(seed)) => {
+            Scheduler::Seeded { seed, slice }
         }
-    }
-
-    fn op_less(&mut self, p_modes: [PMode; 2]) {
-        let a = get(&self.mem, p_modes[0], self.pc + 1);
-        let b = get(&self.mem, p_modes[1], self.pc + 2);
-        let out = self.mem[self.pc + 3] as usize;
-
-        if a < b {
-            self.mem[out] = 1;
-        } else {
-            self.mem[out] = 0;
+        (Some("seeded"), None, None) => panic!("Missing --seed for --scheduler seeded."),
+        (Some("threads"), None, Some(_)) | (Some("round-robin"), None, Some(_)) => {
+            panic!("--seed only applies to --scheduler seeded.")
         }
-        self.pc += 4;
+        (None, None, None) | (Some("threads"), None, None) => Scheduler::Threads,
+        (Some("round-robin"), None, None) => Scheduler::RoundRobin { slice },
+        _ => panic!("Invalid value for --scheduler."),
+    };
+    if scheduler == Scheduler::Threads && record.is_some() {
+        panic!("Can't record a schedule with --scheduler threads.");
     }
+    (rest, Scheduling { scheduler, record })
+}
 
-    fn op_eq(&mut self, p_modes: [PMode; 2]) {
-        let a = get(&self.mem, p_modes[0], self.pc + 1);
-        let b = get(&self.mem, p_modes[1], self.pc + 2);
-        let out = self.mem[self.pc + 3] as usize;
-
-        if a == b {
-            self.mem[out] = 1;
-        } else {
-            self.mem[out] = 0;
-        }
-        self.pc += 4;
-    }
+/// Where `--trace DIR` and `--trace-format FORMAT` ask for traces to go.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceOptions {
+    pub dir: PathBuf,
+    pub format: TraceFormat,
 }
 
-fn get(input: &Vec<Base>, p_mode: PMode, idx: usize) -> Base {
-    match p_mode {
-        PMode::Position => {
-            let pos = input[idx] as usize;
-            input[pos]
+/// Removes the optional `--trace DIR` and `--trace-format json|binary` flags
+/// from `args`, returning the remaining arguments and, if `--trace` was given,
+/// the trace options. The format defaults to JSON.
+fn take_trace(args: Vec<String>) -> (Vec<String>, Option<TraceOptions>) {
+    let mut rest = Vec::new();
+    let mut dir = None;
+    let mut format = TraceFormat::Json;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => {
+                let value = args.next().expect("Missing value for --trace.");
+                dir = Some(PathBuf::from(value));
+            }
+            "--trace-format" => {
+                let value = args.next().expect("Missing value for --trace-format.");
+                format = value.parse().expect("Invalid value for --trace-format.");
+            }
+            _ => rest.push(arg),
         }
-        PMode::Immediate => input[idx],
     }
+    (rest, dir.map(|dir| TraceOptions { dir, format }))
 }
 
-fn op_add(input: &mut Vec<Base>, p_modes: [PMode; 2], pc: usize) {
-    let a = get(input, p_modes[0], pc + 1);
-    let b = get(input, p_modes[1], pc + 2);
-    let out = input[pc + 3] as usize;
-    input[out] = a + b;
-}
-
-fn op_mult(input: &mut Vec<Base>, p_modes: [PMode; 2], pc: usize) {
-    let a = get(input, p_modes[0], pc + 1);
-    let b = get(input, p_modes[1], pc + 2);
-    let out = input[pc + 3] as usize;
-    input[out] = a * b;
+/// What `--search PHASES`, `--amps N`, `--serial` and `--threads N` ask for.
+#[derive(Clone, Debug, PartialEq)]
+struct SearchOptions {
+    phase_set: Vec<Base>,
+    /// Defaults to one amplifier per phase.
+    amps: Option<usize>,
+    wiring: Wiring,
+    /// Defaults to the number of available cores.
+    threads: Option<usize>,
 }
 
-fn parse_instruction(input: Base) -> Result<OpCode, String> {
-    let opcode = input % 100;
-    let mut rem = input / 100;
-    let mut p_modes = Vec::<PMode>::new();
-    while rem > 0 {
-        let p_mode = match rem % 10 {
-            0 => Ok(PMode::Position),
-            1 => Ok(PMode::Immediate),
-            _ => Err("Unknown PMODE"),
-        }?;
-        p_modes.push(p_mode);
-        rem /= 10;
-    }
-
-    match opcode {
-        1 => Ok(OpCode::Add {
-            p_modes: [
-                *p_modes.get(0).unwrap_or(&PMode::Position),
-                *p_modes.get(1).unwrap_or(&PMode::Position),
-            ],
-        }),
-        2 => Ok(OpCode::Multiply {
-            p_modes: [
-                *p_modes.get(0).unwrap_or(&PMode::Position),
-                *p_modes.get(1).unwrap_or(&PMode::Position),
-            ],
-        }),
-        3 => Ok(OpCode::Input),
-        4 => Ok(OpCode::Output {
-            p_modes: [*p_modes.get(0).unwrap_or(&PMode::Position)],
-        }),
-        5 => Ok(OpCode::JmpT {
-            p_modes: [
-                *p_modes.get(0).unwrap_or(&PMode::Position),
-                *p_modes.get(1).unwrap_or(&PMode::Position),
-            ],
-        }),
-        6 => Ok(OpCode::JmpF {
-            p_modes: [
-                *p_modes.get(0).unwrap_or(&PMode::Position),
-                *p_modes.get(1).unwrap_or(&PMode::Position),
-            ],
-        }),
-        7 => Ok(OpCode::Less {
-            p_modes: [
-                *p_modes.get(0).unwrap_or(&PMode::Position),
-                *p_modes.get(1).unwrap_or(&PMode::Position),
-            ],
-        }),
-        8 => Ok(OpCode::Eq {
-            p_modes: [
-                *p_modes.get(0).unwrap_or(&PMode::Position),
-                *p_modes.get(1).unwrap_or(&PMode::Position),
-            ],
-        }),
-        99 => Ok(OpCode::Halt),
-        _ => Err("Unknown OPCODE".to_owned()),
+/// Removes the phase search flags from `args`, returning the remaining
+/// arguments and, if `--search` was given, the search options. Wiring
+/// defaults to a feedback loop.
+fn take_search(args: Vec<String>) -> (Vec<String>, Option<SearchOptions>) {
+    let mut rest = Vec::new();
+    let mut phase_set = None;
+    let mut amps = None;
+    let mut wiring = Wiring::Feedback;
+    let mut threads = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--search" => {
+                let value = args.next().expect("Missing value for --search.");
+                phase_set = Some(parse_phase_set(&value).expect("Invalid value for --search."));
+            }
+            "--amps" => {
+                let value = args.next().expect("Missing value for --amps.");
+                amps = Some(value.parse().expect("Invalid value for --amps."));
+            }
+            "--serial" => wiring = Wiring::Serial,
+            "--threads" => {
+                let value = args.next().expect("Missing value for --threads.");
+                threads = Some(value.parse().expect("Invalid value for --threads."));
+            }
+            _ => rest.push(arg),
+        }
     }
+    let options = phase_set.map(|phase_set| SearchOptions {
+        phase_set,
+        amps,
+        wiring,
+        threads,
+    });
+    (rest, options)
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum OpCode {
-    Add { p_modes: [PMode; 2] },
-    Multiply { p_modes: [PMode; 2] },
-    Input,
-    Output { p_modes: [PMode; 1] },
-    JmpT { p_modes: [PMode; 2] },
-    JmpF { p_modes: [PMode; 2] },
-    Less { p_modes: [PMode; 2] },
-    Eq { p_modes: [PMode; 2] },
-    Halt,
-}
+fn run_search(filename: &str, options: &SearchOptions, limits: Limits) {
+    let input = fs::read_to_string(filename).expect("Failed to read file.");
+    let program = parse_mem_file(&input);
+    let amps = options.amps.unwrap_or(options.phase_set.len());
+    let threads = options.threads.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum PMode {
-    Position,
-    Immediate,
+    match search(
+        &program,
+        &options.phase_set,
+        amps,
+        options.wiring,
+        limits,
+        threads,
+    ) {
+        Ok(Some(best)) => {
+            println!("Best phase setting: {}", format_phases(&best.phases));
+            println!("{}", best.signal);
+        }
+        Ok(None) => {
+            eprintln!("No phase setting produced a signal.");
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_parse_cmd() {
-        let expected = Ok(OpCode::Add {
-            p_modes: [PMode::Position, PMode::Position],
-        });
-        assert_eq!(parse_instruction(1), expected);
-    }
-
-    #[test]
-    fn test_parse_cmd_modes() {
-        let expected = Ok(OpCode::Multiply {
-            p_modes: [PMode::Position, PMode::Immediate],
-        });
-        assert_eq!(parse_instruction(1002), expected);
-    }
-
-    #[test]
-    fn test_add() {
-        let mut input = vec![1, 5, 6, 0, 99, 2, 3];
-        op_add(&mut input, [PMode::Position, PMode::Position], 0);
-
-        assert_eq!(input, &[5, 5, 6, 0, 99, 2, 3]);
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
     }
 
     #[test]
-    fn test_add_neg() {
-        let mut input = vec![1, 5, 6, 0, 99, 2, -3];
-        op_add(&mut input, [PMode::Position, PMode::Position], 0);
-
-        assert_eq!(input, &[-1, 5, 6, 0, 99, 2, -3]);
+    fn test_take_limits() {
+        let (rest, limits) = take_limits(args(&[
+            "day7",
+            "--timeout-ms",
+            "250",
+            "in.txt",
+            "--max-instructions",
+            "9",
+        ]));
+        assert_eq!(rest, &["day7", "in.txt"]);
+        assert_eq!(limits.max_instructions, Some(9));
+        assert_eq!(limits.max_duration, Some(Duration::from_millis(250)));
     }
 
     #[test]
-    fn test_add_imm() {
-        let mut input = vec![1, 5, 6, 3, 99, 2, 3];
-        op_add(&mut input, [PMode::Immediate, PMode::Position], 0);
-
-        assert_eq!(input, &[1, 5, 6, 8, 99, 2, 3]);
+    fn test_take_search() {
+        let (rest, options) = take_search(args(&[
+            "day7", "in.txt", "--search", "0-4", "--serial", "--amps", "3",
+        ]));
+        assert_eq!(rest, &["day7", "in.txt"]);
+        assert_eq!(
+            options,
+            Some(SearchOptions {
+                phase_set: vec![0, 1, 2, 3, 4],
+                amps: Some(3),
+                wiring: Wiring::Serial,
+                threads: None,
+            })
+        );
     }
 
     #[test]
-    fn test_mult() {
-        let mut input = vec![1, 5, 6, 0, 99, 2, 3];
-        op_mult(&mut input, [PMode::Position, PMode::Position], 0);
-
-        assert_eq!(input, &[6, 5, 6, 0, 99, 2, 3]);
+    fn test_take_network() {
+        let (rest, network) = take_network(args(&["day7", "--network", "ring.net"]));
+        assert_eq!(rest, &["day7"]);
+        assert_eq!(network.as_deref(), Some("ring.net"));
     }
 
     #[test]
-    fn test_op_input() {
-        let (tx, rx) = channel();
-        tx.send(42).unwrap();
-        let mut cmp = Computer {
-            in_buf: rx,
-            out_buf: tx,
-            mem: vec![3, 5, 6, 0, 99, 2, 3],
-            pc: 0,
-            halted: false,
-            name: "TestUnit",
-        };
-        cmp.op_input(0);
-
-        assert_eq!(cmp.mem, &[3, 5, 6, 0, 99, 42, 3]);
+    fn test_take_scheduler() {
+        let (rest, scheduling) = take_scheduler(args(&[
+            "day7",
+            "--slice",
+            "50",
+            "--scheduler",
+            "round-robin",
+        ]));
+        assert_eq!(rest, &["day7"]);
+        assert_eq!(scheduling.scheduler, Scheduler::RoundRobin { slice: 50 });
+
+        let (_, scheduling) = take_scheduler(args(&["day7"]));
+        assert_eq!(scheduling.scheduler, Scheduler::Threads);
+        assert_eq!(scheduling.record, None);
     }
 
     #[test]
-    fn test_parse() {
-        let res = parse_mem_file("1,0,0,0,99");
-        assert_eq!(res, &[1, 0, 0, 0, 99]);
+    fn test_take_scheduler_seeded() {
+        let (rest, scheduling) = take_scheduler(args(&[
+            "day7",
+            "--seed",
+            "42",
+            "--record-schedule",
+            "run.sched",
+        ]));
+        assert_eq!(rest, &["day7"]);
+        assert_eq!(
+            scheduling,
+            Scheduling {
+                scheduler: Scheduler::Seeded {
+                    seed: 42,
+                    slice: 1000
+                },
+                record: Some(PathBuf::from("run.sched")),
+            }
+        );
+
+        let (_, scheduling) = take_scheduler(args(&[
+            "day7",
+            "--replay-schedule",
+            "run.sched",
+            "--slice",
+            "5",
+        ]));
+        assert_eq!(
+            scheduling.scheduler,
+            Scheduler::Replay {
+                path: PathBuf::from("run.sched"),
+                slice: 5
+            }
+        );
     }
 
     #[test]
-    fn test_interpret_case1() {
-        let (tx, rx) = channel();
-        let mut cmp = Computer {
-            in_buf: rx,
-            out_buf: tx,
-            mem: vec![1, 0, 0, 0, 99],
-            pc: 0,
-            halted: false,
-            name: "TestUnit",
-        };
-        while !cmp.halted {
-            cmp.step();
-        }
-        assert_eq!(cmp.halted, true);
-        assert_eq!(cmp.mem, &[2, 0, 0, 0, 99]);
+    #[should_panic(expected = "--seed only applies to --scheduler seeded.")]
+    fn test_take_scheduler_seed_without_seeded() {
+        take_scheduler(args(&["day7", "--scheduler", "round-robin", "--seed", "1"]));
     }
 
     #[test]
-    fn test_interpret_case2() {
-        let (tx, rx) = channel();
-        let mut cmp = Computer {
-            in_buf: rx,
-            out_buf: tx,
-            mem: vec![2, 3, 0, 3, 99],
-            pc: 0,
-            halted: false,
-            name: "TestUnit",
-        };
-        while !cmp.halted {
-            cmp.step();
-        }
-        assert_eq!(cmp.halted, true);
-        assert_eq!(cmp.mem, &[2, 3, 0, 6, 99]);
+    #[should_panic(expected = "Missing --seed for --scheduler seeded.")]
+    fn test_take_scheduler_seeded_without_seed() {
+        take_scheduler(args(&["day7", "--scheduler", "seeded"]));
     }
 
     #[test]
-    fn test_interpret_case3() {
-        let (tx, rx) = channel();
-        let mut cmp = Computer {
-            in_buf: rx,
-            out_buf: tx,
-            mem: vec![2, 4, 4, 5, 99, 0],
-            pc: 0,
-            halted: false,
-            name: "TestUnit",
-        };
-        while !cmp.halted {
-            cmp.step();
-        }
-        assert_eq!(cmp.halted, true);
-        assert_eq!(cmp.mem, &[2, 4, 4, 5, 99, 9801]);
-    }
-
-    #[test]
-    fn test_interpret_case4() {
-        let (tx, rx) = channel();
-        let mut cmp = Computer {
-            in_buf: rx,
-            out_buf: tx,
-            mem: vec![1, 1, 1, 4, 99, 5, 6, 0, 99],
-            pc: 0,
-            halted: false,
-            name: "TestUnit",
-        };
-        while !cmp.halted {
-            cmp.step();
-        }
-        assert_eq!(cmp.halted, true);
-        assert_eq!(cmp.mem, &[30, 1, 1, 4, 2, 5, 6, 0, 99]);
+    fn test_take_trace() {
+        let (rest, trace) = take_trace(args(&[
+            "day7",
+            "--trace-format",
+            "binary",
+            "--trace",
+            "out",
+            "in.txt",
+        ]));
+        assert_eq!(rest, &["day7", "in.txt"]);
+        assert_eq!(
+            trace,
+            Some(TraceOptions {
+                dir: PathBuf::from("out"),
+                format: TraceFormat::Binary,
+            })
+        );
+
+        let (_, trace) = take_trace(args(&["day7"]));
+        assert_eq!(trace, None);
     }
 }
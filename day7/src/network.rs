@@ -0,0 +1,614 @@
+use crate::search::{amp_name, Wiring};
+use crate::TraceOptions;
+use intcode::{trace_file, Base, Closed, Computer, Input, Limits, Output, VmError};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// A machine in a `Topology`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineSpec {
+    pub name: String,
+    pub program: Vec<Base>,
+    /// Values the machine reads before anything sent over links.
+    pub inputs: Vec<Base>,
+}
+
+/// Describes a network of machines and the directed links between them.
+///
+/// Every output a machine produces is sent along each of its outgoing links
+/// (fan-out). A machine with several incoming links reads their values
+/// merged in the order they arrive (fan-in).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Topology {
+    machines: Vec<MachineSpec>,
+    /// Pairs of machine indices, from sender to receiver.
+    links: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TopologyError {
+    DuplicateMachine(String),
+    UnknownMachine(String),
+    /// A problem in a topology file, with its 1-based line number.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TopologyError::DuplicateMachine(name) => {
+                write!(f, "machine `{}` is already defined", name)
+            }
+            TopologyError::UnknownMachine(name) => write!(f, "unknown machine `{}`", name),
+            TopologyError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for TopologyError {}
+
+impl Topology {
+    pub fn new() -> Topology {
+        Topology::default()
+    }
+
+    /// One amplifier per phase, named `A`, `B`, ..., each linked to the
+    /// next. The first also reads the starting signal, 0. With
+    /// `Wiring::Feedback` the last is linked back to the first.
+    pub fn amplifiers(program: &[Base], phases: &[Base], wiring: Wiring) -> Topology {
+        let mut topology = Topology::new();
+        for (i, &phase) in phases.iter().enumerate() {
+            let inputs = if i == 0 { vec![phase, 0] } else { vec![phase] };
+            topology.machines.push(MachineSpec {
+                name: amp_name(i),
+                program: program.to_vec(),
+                inputs,
+            });
+            if i > 0 {
+                topology.links.push((i - 1, i));
+            }
+        }
+        if wiring == Wiring::Feedback && !phases.is_empty() {
+            topology.links.push((phases.len() - 1, 0));
+        }
+        topology
+    }
+
+    /// Adds a machine, returning its index.
+    pub fn add_machine(
+        &mut self,
+        name: &str,
+        program: Vec<Base>,
+        inputs: &[Base],
+    ) -> Result<usize, TopologyError> {
+        if self.index(name).is_some() {
+            return Err(TopologyError::DuplicateMachine(name.to_owned()));
+        }
+        self.machines.push(MachineSpec {
+            name: name.to_owned(),
+            program,
+            inputs: inputs.to_vec(),
+        });
+        Ok(self.machines.len() - 1)
+    }
+
+    /// Sends every output of `from` to `to`.
+    pub fn link(&mut self, from: &str, to: &str) -> Result<(), TopologyError> {
+        let lookup = |name: &str| {
+            self.index(name)
+                .ok_or_else(|| TopologyError::UnknownMachine(name.to_owned()))
+        };
+        let link = (lookup(from)?, lookup(to)?);
+        if !self.links.contains(&link) {
+            self.links.push(link);
+        }
+        Ok(())
+    }
+
+    pub fn machines(&self) -> &[MachineSpec] {
+        &self.machines
+    }
+
+    /// The machines `from` sends its outputs to.
+    pub fn targets(&self, from: usize) -> Vec<usize> {
+        self.links
+            .iter()
+            .filter(|&&(f, _)| f == from)
+            .map(|&(_, to)| to)
+            .collect()
+    }
+
+    /// The machines that send their outputs to `to`.
+    pub fn sources(&self, to: usize) -> Vec<usize> {
+        self.links
+            .iter()
+            .filter(|&&(_, t)| t == to)
+            .map(|&(from, _)| from)
+            .collect()
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.machines.iter().position(|m| m.name == name)
+    }
+
+    /// Reads a topology file. Each line declares a machine or links:
+    ///
+    /// ```text
+    /// # name  program     initial inputs
+    /// machine A amp.txt 9 0
+    /// machine B amp.txt 8
+    /// machine C amp.txt 7
+    /// link A -> B C    # fan-out: A's outputs go to both B and C
+    /// link B -> C      # fan-in: C reads from A and B
+    /// link C -> A
+    /// ```
+    ///
+    /// `load` reads the program named on a `machine` line.
+    pub fn parse<F>(text: &str, mut load: F) -> Result<Topology, TopologyError>
+    where
+        F: FnMut(&str) -> Result<Vec<Base>, String>,
+    {
+        let mut topology = Topology::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| TopologyError::Parse {
+                line: i + 1,
+                message,
+            };
+            let code = match line.find('#') {
+                Some(idx) => &line[..idx],
+                None => line,
+            };
+            let words: Vec<&str> = code.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["machine", name, program, inputs @ ..] => {
+                    let inputs = inputs
+                        .iter()
+                        .map(|v| v.parse().map_err(|_| format!("invalid input `{}`", v)))
+                        .collect::<Result<Vec<Base>, String>>()
+                        .map_err(error)?;
+                    let program = load(program).map_err(error)?;
+                    topology
+                        .add_machine(name, program, &inputs)
+                        .map_err(|err| error(err.to_string()))?;
+                }
+                ["link", from, "->", targets @ ..] if !targets.is_empty() => {
+                    for to in targets {
+                        topology
+                            .link(from, to)
+                            .map_err(|err| error(err.to_string()))?;
+                    }
+                }
+                [word, ..] if *word == "machine" || *word == "link" => {
+                    return Err(error(format!("malformed `{}` line", word)));
+                }
+                [word, ..] => return Err(error(format!("unknown directive `{}`", word))),
+            }
+        }
+        Ok(topology)
+    }
+}
+
+/// How one machine in a network finished.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineResult {
+    pub name: String,
+    /// Every value the machine output, in order.
+    pub outputs: Vec<Base>,
+    /// Why the machine stopped, if it didn't halt. Machines caught in a
+    /// deadlock are listed in `NetworkRun::deadlock` instead.
+    pub error: Option<VmError>,
+}
+
+/// A machine caught in a deadlock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blocked {
+    pub name: String,
+    /// The input instruction it is stuck on.
+    pub pc: usize,
+    /// The machines linked to its input that are still running, and so
+    /// might still send it something.
+    pub sources: Vec<String>,
+}
+
+/// Every machine still running was waiting for input that none of them
+/// could send.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deadlock {
+    pub machines: Vec<Blocked>,
+}
+
+impl fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "deadlock: every running machine is waiting for input")?;
+        for m in &self.machines {
+            write!(
+                f,
+                "\n  {} pc={} waiting on {}",
+                m.name,
+                m.pc,
+                m.sources.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Deadlock {}
+
+/// The outcome of running a network. Results are in declaration order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkRun {
+    pub results: Vec<MachineResult>,
+    pub deadlock: Option<Deadlock>,
+}
+
+impl NetworkRun {
+    /// The last value the machine declared last produced, which for an
+    /// amplifier chain is the thruster signal.
+    pub fn signal(&self) -> Option<Base> {
+        self.results.last().and_then(|r| r.outputs.last()).copied()
+    }
+}
+
+#[derive(Default)]
+struct Mailbox {
+    queue: VecDeque<Base>,
+    waiting: bool,
+    stopped: bool,
+}
+
+struct Board {
+    mailboxes: Vec<Mailbox>,
+    /// Set once a deadlock is found, to the machines caught in it.
+    deadlocked: Option<Vec<usize>>,
+}
+
+/// The queues between the machines of a threaded network, and what each
+/// machine is doing, kept under one lock so that a deadlock can be seen.
+struct Switchboard {
+    board: Mutex<Board>,
+    changed: Condvar,
+    sources: Vec<Vec<usize>>,
+    targets: Vec<Vec<usize>>,
+}
+
+impl Switchboard {
+    fn new(topology: &Topology) -> Switchboard {
+        let n = topology.machines.len();
+        Switchboard {
+            board: Mutex::new(Board {
+                mailboxes: (0..n).map(|_| Mailbox::default()).collect(),
+                deadlocked: None,
+            }),
+            changed: Condvar::new(),
+            sources: (0..n).map(|i| topology.sources(i)).collect(),
+            targets: (0..n).map(|i| topology.targets(i)).collect(),
+        }
+    }
+
+    fn send(&self, from: usize, value: Base) {
+        let mut board = self.board.lock().unwrap();
+        for &to in &self.targets[from] {
+            let mailbox = &mut board.mailboxes[to];
+            if !mailbox.stopped {
+                mailbox.queue.push_back(value);
+            }
+        }
+        self.changed.notify_all();
+    }
+
+    /// Waits for a value for machine `idx`. Returns `None` once nothing can
+    /// arrive: every source has stopped, the machines are deadlocked or
+    /// `deadline` has passed.
+    fn receive(&self, idx: usize, deadline: Option<Instant>) -> Option<Base> {
+        let mut board = self.board.lock().unwrap();
+        loop {
+            if let Some(value) = board.mailboxes[idx].queue.pop_front() {
+                board.mailboxes[idx].waiting = false;
+                return Some(value);
+            }
+            if board.deadlocked.is_some() || self.closed(&board, idx) {
+                return None;
+            }
+            board.mailboxes[idx].waiting = true;
+            self.check_deadlock(&mut board);
+            if board.deadlocked.is_some() {
+                return None;
+            }
+            board = match deadline {
+                None => self.changed.wait(board).unwrap(),
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.as_nanos() == 0 {
+                        board.mailboxes[idx].waiting = false;
+                        return None;
+                    }
+                    self.changed.wait_timeout(board, timeout).unwrap().0
+                }
+            };
+        }
+    }
+
+    fn stop(&self, idx: usize) {
+        let mut board = self.board.lock().unwrap();
+        board.mailboxes[idx].stopped = true;
+        board.mailboxes[idx].waiting = false;
+        self.check_deadlock(&mut board);
+        self.changed.notify_all();
+    }
+
+    /// Whether machine `idx` can never receive another value.
+    fn closed(&self, board: &Board, idx: usize) -> bool {
+        self.sources[idx]
+            .iter()
+            .all(|&from| board.mailboxes[from].stopped)
+    }
+
+    fn check_deadlock(&self, board: &mut Board) {
+        if board.deadlocked.is_some() {
+            return;
+        }
+        let live: Vec<usize> = (0..board.mailboxes.len())
+            .filter(|&i| !board.mailboxes[i].stopped)
+            .collect();
+        // A machine whose sources have all stopped will see its input close
+        // and stop too, so it isn't stuck yet.
+        let stuck = live.iter().all(|&i| {
+            let mailbox = &board.mailboxes[i];
+            mailbox.waiting && mailbox.queue.is_empty() && !self.closed(board, i)
+        });
+        if !live.is_empty() && stuck {
+            board.deadlocked = Some(live);
+            self.changed.notify_all();
+        }
+    }
+}
+
+/// Reads a machine's values from the switchboard.
+struct Inbox {
+    board: Arc<Switchboard>,
+    idx: usize,
+}
+
+impl Input for Inbox {
+    fn read(&mut self) -> Option<Base> {
+        self.board.receive(self.idx, None)
+    }
+
+    fn read_before(&mut self, deadline: Instant) -> Option<Base> {
+        self.board.receive(self.idx, Some(deadline))
+    }
+}
+
+/// Records each output and sends it along every outgoing link. Machines that
+/// have already stopped simply miss the value.
+struct Fanout {
+    board: Arc<Switchboard>,
+    idx: usize,
+    log: Vec<Base>,
+}
+
+impl Output for Fanout {
+    fn write(&mut self, value: Base) -> Result<(), Closed> {
+        self.log.push(value);
+        self.board.send(self.idx, value);
+        Ok(())
+    }
+}
+
+/// Creates the machine `spec` describes, with its initial inputs queued and,
+/// if asked for, a tracer writing to its own file.
+pub fn new_machine<I: Input, O: Output>(
+    spec: &MachineSpec,
+    input: I,
+    output: O,
+    limits: Limits,
+    trace: Option<&TraceOptions>,
+) -> Computer<I, O> {
+    let mut cmp = Computer::new(&spec.name, spec.program.clone(), input, output);
+    cmp.set_limits(limits);
+    for &value in &spec.inputs {
+        cmp.push_input(value);
+    }
+    if let Some(trace) = trace {
+        let tracer =
+            trace_file(&trace.dir, &spec.name, trace.format).expect("Failed to create trace file.");
+        cmp.set_tracer(tracer);
+    }
+    cmp
+}
+
+/// Flushes the machine's trace, if it has one.
+pub fn finish_trace<I: Input, O: Output>(cmp: &mut Computer<I, O>) {
+    if let Some(mut tracer) = cmp.take_tracer() {
+        if let Err(err) = tracer.finish() {
+            println!("[{}] failed to write trace: {}", cmp.name, err);
+        }
+    }
+}
+
+/// Runs every machine of `topology` on its own thread until they have all
+/// stopped. If every machine still running ends up waiting for input that
+/// none of them will send, they are all stopped and the deadlock reported.
+pub fn run_threaded(
+    topology: &Topology,
+    limits: Limits,
+    trace: Option<&TraceOptions>,
+) -> NetworkRun {
+    let board = Arc::new(Switchboard::new(topology));
+
+    let threads: Vec<_> = topology
+        .machines
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            let input = Inbox {
+                board: board.clone(),
+                idx: i,
+            };
+            let output = Fanout {
+                board: board.clone(),
+                idx: i,
+                log: Vec::new(),
+            };
+            let mut cmp = new_machine(spec, input, output, limits, trace);
+            let board = board.clone();
+            thread::spawn(move || {
+                let error = cmp.compute().err();
+                board.stop(i);
+                finish_trace(&mut cmp);
+                MachineResult {
+                    name: cmp.name.clone(),
+                    outputs: cmp.output().log.clone(),
+                    error,
+                }
+            })
+        })
+        .collect();
+    let mut results: Vec<MachineResult> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+    let deadlocked = board.board.lock().unwrap().deadlocked.take();
+    let deadlock = deadlocked.map(|machines| Deadlock {
+        machines: machines
+            .iter()
+            .map(|&i| Blocked {
+                name: results[i].name.clone(),
+                pc: results[i].error.take().map_or(0, |err| err.pc()),
+                sources: board.sources[i]
+                    .iter()
+                    .filter(|from| machines.contains(from))
+                    .map(|&from| results[from].name.clone())
+                    .collect(),
+            })
+            .collect(),
+    });
+    NetworkRun { results, deadlock }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode::parse_mem_file;
+
+    #[test]
+    fn test_amplifiers() {
+        let feedback = parse_mem_file(include_str!("../testA2.txt"));
+        let topology = Topology::amplifiers(&feedback, &[9, 8, 7, 6, 5], Wiring::Feedback);
+        assert_eq!(topology.links, &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)]);
+        let run = run_threaded(&topology, Limits::none(), None);
+        let results = &run.results;
+        assert_eq!(run.signal(), Some(139_629_729));
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[4].name, "E");
+        assert_eq!(results[4].outputs.last(), Some(&139_629_729));
+        assert!(results.iter().all(|r| r.error.is_none()));
+    }
+
+    #[test]
+    fn test_fan_out_and_fan_in() {
+        // `src` outputs its two inputs; `double` and `triple` each transform
+        // both; `sum` adds up the four values it receives.
+        let text = "
+            machine src echo 4 5
+            machine double double
+            machine triple triple
+            machine sum sum
+            link src -> double triple   # fan-out
+            link double -> sum          # fan-in
+            link triple -> sum
+        ";
+        let topology = Topology::parse(text, |name| {
+            Ok(parse_mem_file(match name {
+                "echo" => "3,0,4,0,3,0,4,0,99",
+                "double" => "3,0,102,2,0,0,4,0,3,0,102,2,0,0,4,0,99",
+                "triple" => "3,0,102,3,0,0,4,0,3,0,102,3,0,0,4,0,99",
+                "sum" => "3,0,3,1,1,0,1,0,3,1,1,0,1,0,3,1,1,0,1,0,4,0,99",
+                _ => return Err(format!("no program `{}`", name)),
+            }))
+        })
+        .unwrap();
+        assert_eq!(topology.links, &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+        let results = run_threaded(&topology, Limits::none(), None).results;
+        assert_eq!(results[1].outputs, &[8, 10]);
+        assert_eq!(results[2].outputs, &[12, 15]);
+        assert_eq!(results[3].outputs, &[2 * 9 + 3 * 9]);
+    }
+
+    #[test]
+    fn test_closed_input() {
+        let mut topology = Topology::new();
+        topology
+            .add_machine("lonely", parse_mem_file("3,0,3,0,99"), &[1])
+            .unwrap();
+        let run = run_threaded(&topology, Limits::none(), None);
+        assert_eq!(run.deadlock, None);
+        assert_eq!(
+            run.results[0].error,
+            Some(VmError::InputClosed {
+                pc: 2,
+                instruction: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_deadlock() {
+        // Each waits to hear from the other before saying anything. `idle`
+        // halts straight away and isn't part of the deadlock.
+        let text = "
+            machine A wait
+            machine B wait
+            machine idle halt
+            link A -> B
+            link B -> A
+            link idle -> A
+        ";
+        let topology = Topology::parse(text, |name| {
+            Ok(parse_mem_file(match name {
+                "wait" => "1,0,0,0,3,0,4,0,99",
+                _ => "99",
+            }))
+        })
+        .unwrap();
+        let run = run_threaded(&topology, Limits::none(), None);
+        let deadlock = run.deadlock.unwrap();
+        assert_eq!(
+            deadlock.to_string(),
+            "deadlock: every running machine is waiting for input\n  \
+             A pc=4 waiting on B\n  \
+             B pc=4 waiting on A"
+        );
+        assert!(run.results.iter().all(|r| r.error.is_none()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let load = |_: &str| -> Result<Vec<Base>, String> { Ok(vec![99]) };
+        let error = |text: &str| Topology::parse(text, load).unwrap_err().to_string();
+        assert_eq!(
+            error("machine A p\nmachine A p"),
+            "line 2: machine `A` is already defined"
+        );
+        assert_eq!(
+            error("machine A p\nlink A -> B"),
+            "line 2: unknown machine `B`"
+        );
+        assert_eq!(error("link A"), "line 1: malformed `link` line");
+        assert_eq!(error("machine A p x"), "line 1: invalid input `x`");
+        assert_eq!(error("wire A B"), "line 1: unknown directive `wire`");
+        assert_eq!(
+            Topology::parse("machine A missing", |_| Err("not found".to_owned()))
+                .unwrap_err()
+                .to_string(),
+            "line 1: not found"
+        );
+    }
+}
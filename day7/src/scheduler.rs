@@ -0,0 +1,537 @@
+use crate::network::{
+    finish_trace, new_machine, Blocked, Deadlock, MachineResult, NetworkRun, Topology,
+};
+use crate::TraceOptions;
+use intcode::{Computer, Limits, StepOutcome, VmError};
+use std::collections::VecDeque;
+
+/// One scheduling decision: run `machine` for up to `steps` instructions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Turn {
+    pub machine: usize,
+    pub steps: u64,
+}
+
+/// Decides which machine a cooperative scheduler runs next, and for how
+/// long.
+pub trait Policy {
+    /// Picks one of `ready`, the indices of the machines that can run, in
+    /// ascending order and never empty. `slice` is the configured time
+    /// slice.
+    fn next(&mut self, ready: &[usize], slice: u64) -> Turn;
+}
+
+/// Runs ready machines in turn, in declaration order, for a full slice each.
+#[derive(Clone, Debug, Default)]
+pub struct RoundRobin {
+    last: Option<usize>,
+}
+
+impl Policy for RoundRobin {
+    fn next(&mut self, ready: &[usize], slice: u64) -> Turn {
+        let machine = match self.last {
+            Some(last) => *ready.iter().find(|&&i| i > last).unwrap_or(&ready[0]),
+            None => ready[0],
+        };
+        self.last = Some(machine);
+        Turn {
+            machine,
+            steps: slice,
+        }
+    }
+}
+
+/// Picks a random ready machine and runs it for a random part of the slice,
+/// from a seeded generator, so the same seed always gives the same run.
+/// Different seeds explore different interleavings.
+#[derive(Clone, Debug)]
+pub struct Seeded {
+    state: u64,
+}
+
+impl Seeded {
+    pub fn new(seed: u64) -> Seeded {
+        Seeded { state: seed }
+    }
+
+    // SplitMix64.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Policy for Seeded {
+    fn next(&mut self, ready: &[usize], slice: u64) -> Turn {
+        let machine = ready[(self.next_u64() % ready.len() as u64) as usize];
+        let steps = 1 + self.next_u64() % slice.max(1);
+        Turn { machine, steps }
+    }
+}
+
+/// The turns a cooperative run took, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    pub turns: Vec<Turn>,
+}
+
+impl Schedule {
+    /// Writes one turn per line, as the machine index and the step budget.
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# machine steps\n");
+        for turn in &self.turns {
+            text.push_str(&format!("{} {}\n", turn.machine, turn.steps));
+        }
+        text
+    }
+
+    /// Reads a schedule written by `to_text`. Blank lines and `#` comments
+    /// are ignored.
+    pub fn parse(text: &str) -> Result<Schedule, String> {
+        let mut turns = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let code = match line.find('#') {
+                Some(idx) => &line[..idx],
+                None => line,
+            };
+            let words: Vec<&str> = code.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                [machine, steps] => match (machine.parse(), steps.parse()) {
+                    (Ok(machine), Ok(steps)) => turns.push(Turn { machine, steps }),
+                    _ => return Err(format!("line {}: invalid turn `{}`", i + 1, line.trim())),
+                },
+                _ => return Err(format!("line {}: invalid turn `{}`", i + 1, line.trim())),
+            }
+        }
+        Ok(Schedule { turns })
+    }
+}
+
+/// Passes decisions through from another policy, keeping a copy of each.
+pub struct Recorder<'a> {
+    policy: &'a mut dyn Policy,
+    pub schedule: Schedule,
+}
+
+impl<'a> Recorder<'a> {
+    pub fn new(policy: &'a mut dyn Policy) -> Recorder<'a> {
+        Recorder {
+            policy,
+            schedule: Schedule::default(),
+        }
+    }
+}
+
+impl<'a> Policy for Recorder<'a> {
+    fn next(&mut self, ready: &[usize], slice: u64) -> Turn {
+        let turn = self.policy.next(ready, slice);
+        self.schedule.turns.push(turn);
+        turn
+    }
+}
+
+/// Takes the turns of a recorded schedule in order. Given the same network
+/// and inputs, this reproduces the recorded run exactly.
+///
+/// If the run stops matching the schedule, because a recorded machine isn't
+/// ready or the turns run out, the replay notes the turn and carries on
+/// round-robin.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    schedule: Schedule,
+    next: usize,
+    diverged: Option<usize>,
+    fallback: RoundRobin,
+}
+
+impl Replay {
+    pub fn new(schedule: Schedule) -> Replay {
+        Replay {
+            schedule,
+            next: 0,
+            diverged: None,
+            fallback: RoundRobin::default(),
+        }
+    }
+
+    /// The index of the first turn that didn't match the schedule.
+    pub fn diverged(&self) -> Option<usize> {
+        self.diverged
+    }
+}
+
+impl Policy for Replay {
+    fn next(&mut self, ready: &[usize], slice: u64) -> Turn {
+        let idx = self.next;
+        self.next += 1;
+        match self.schedule.turns.get(idx) {
+            Some(&turn) if self.diverged.is_none() && ready.contains(&turn.machine) => turn,
+            _ => {
+                self.diverged.get_or_insert(idx);
+                self.fallback.next(ready, slice)
+            }
+        }
+    }
+}
+
+struct Machine {
+    cmp: Computer,
+    /// Waiting for input that hasn't arrived.
+    blocked: bool,
+    stopped: bool,
+    error: Option<VmError>,
+}
+
+/// Runs every machine of `topology` on the calling thread. `policy` picks
+/// which ready machine goes next and how many instructions it may execute,
+/// given the time slice `slice`. The machine runs until it has used them,
+/// needs input it doesn't have or stops. Outputs are delivered to every
+/// linked machine as soon as they are produced, so the schedule fixes the
+/// whole run.
+///
+/// For networks where no machine reads from more than one other, the
+/// outputs are the same as `run_threaded` gives, as are errors and
+/// deadlocks. With fan-in, values are merged in the order the schedule
+/// produces them.
+pub fn run_cooperative(
+    topology: &Topology,
+    limits: Limits,
+    trace: Option<&TraceOptions>,
+    policy: &mut dyn Policy,
+    slice: u64,
+) -> NetworkRun {
+    let n = topology.machines().len();
+    let sources: Vec<Vec<usize>> = (0..n).map(|i| topology.sources(i)).collect();
+    let targets: Vec<Vec<usize>> = (0..n).map(|i| topology.targets(i)).collect();
+    let mut machines: Vec<Machine> = topology
+        .machines()
+        .iter()
+        .map(|spec| Machine {
+            cmp: new_machine(spec, VecDeque::new(), Vec::new(), limits, trace),
+            blocked: false,
+            stopped: false,
+            error: None,
+        })
+        .collect();
+
+    let mut deadlock = None;
+    loop {
+        let ready: Vec<usize> = (0..n)
+            .filter(|&i| !machines[i].stopped && !machines[i].blocked)
+            .collect();
+        if ready.is_empty() {
+            // Machines that can never get more input stop with it closed,
+            // as they would with threads. Otherwise they are stuck.
+            let closed: Vec<usize> = (0..n)
+                .filter(|&i| !machines[i].stopped)
+                .filter(|&i| sources[i].iter().all(|&from| machines[from].stopped))
+                .collect();
+            if !closed.is_empty() {
+                for i in closed {
+                    let cmp = &machines[i].cmp;
+                    let error = VmError::InputClosed {
+                        pc: cmp.pc,
+                        instruction: cmp.mem().get(cmp.pc).unwrap_or(0),
+                    };
+                    stop(&mut machines[i], Some(error));
+                }
+                continue;
+            }
+            let live: Vec<usize> = (0..n).filter(|&i| !machines[i].stopped).collect();
+            if !live.is_empty() {
+                deadlock = Some(Deadlock {
+                    machines: live
+                        .iter()
+                        .map(|&i| Blocked {
+                            name: machines[i].cmp.name.clone(),
+                            pc: machines[i].cmp.pc,
+                            sources: sources[i]
+                                .iter()
+                                .filter(|&&from| !machines[from].stopped)
+                                .map(|&from| machines[from].cmp.name.clone())
+                                .collect(),
+                        })
+                        .collect(),
+                });
+                for i in live {
+                    stop(&mut machines[i], None);
+                }
+            }
+            break;
+        }
+
+        let Turn { machine: i, steps } = policy.next(&ready, slice);
+        for _ in 0..steps.max(1) {
+            match machines[i].cmp.step() {
+                Ok(StepOutcome::Continue) => {}
+                Ok(StepOutcome::Output(value)) => {
+                    for &to in &targets[i] {
+                        let target = &mut machines[to];
+                        if !target.stopped {
+                            target.cmp.push_input(value);
+                            target.blocked = false;
+                        }
+                    }
+                }
+                Ok(StepOutcome::NeedsInput) => {
+                    machines[i].blocked = true;
+                    break;
+                }
+                Ok(StepOutcome::Halted) => {
+                    stop(&mut machines[i], None);
+                    break;
+                }
+                Err(err) => {
+                    stop(&mut machines[i], Some(err));
+                    break;
+                }
+            }
+        }
+    }
+
+    let results = machines
+        .into_iter()
+        .map(|m| MachineResult {
+            name: m.cmp.name.clone(),
+            outputs: m.cmp.output().clone(),
+            error: m.error,
+        })
+        .collect();
+    NetworkRun { results, deadlock }
+}
+
+fn stop(machine: &mut Machine, error: Option<VmError>) {
+    machine.stopped = true;
+    machine.error = error;
+    finish_trace(&mut machine.cmp);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::run_threaded;
+    use crate::search::Wiring;
+    use intcode::{parse_mem_file, Base};
+
+    fn amplifiers(source: &str, phases: &[Base], wiring: Wiring) -> Topology {
+        Topology::amplifiers(&parse_mem_file(source), phases, wiring)
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut policy = RoundRobin::default();
+        let picks: Vec<usize> = [&[0, 1, 2][..], &[0, 1, 2], &[0, 2], &[0, 1, 2], &[1]]
+            .iter()
+            .map(|ready| policy.next(ready, 10).machine)
+            .collect();
+        assert_eq!(picks, &[0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_matches_threaded() {
+        let networks = [
+            amplifiers(
+                include_str!("../testA2.txt"),
+                &[9, 8, 7, 6, 5],
+                Wiring::Feedback,
+            ),
+            amplifiers(
+                include_str!("../testB2.txt"),
+                &[9, 7, 8, 5, 6],
+                Wiring::Feedback,
+            ),
+            amplifiers(
+                include_str!("../testB.txt"),
+                &[0, 1, 2, 3, 4],
+                Wiring::Serial,
+            ),
+        ];
+        for topology in &networks {
+            let threaded = run_threaded(topology, Limits::none(), None);
+            for &slice in &[1, 7, 1000] {
+                let run = run_cooperative(
+                    topology,
+                    Limits::none(),
+                    None,
+                    &mut RoundRobin::default(),
+                    slice,
+                );
+                assert_eq!(run, threaded);
+            }
+        }
+        let run = run_cooperative(
+            &networks[0],
+            Limits::none(),
+            None,
+            &mut RoundRobin::default(),
+            5,
+        );
+        assert_eq!(run.signal(), Some(139_629_729));
+        assert_eq!(run.results[0].outputs.len(), 5);
+    }
+
+    #[test]
+    fn test_many_machines() {
+        // A chain of 300 machines that each add 1 to what they pass on.
+        let program = "3,0,1001,0,1,0,4,0,99";
+        let mut topology = Topology::new();
+        for i in 0..300 {
+            let inputs: &[Base] = if i == 0 { &[0] } else { &[] };
+            topology
+                .add_machine(&i.to_string(), parse_mem_file(program), inputs)
+                .unwrap();
+            if i > 0 {
+                topology.link(&(i - 1).to_string(), &i.to_string()).unwrap();
+            }
+        }
+        let run = run_cooperative(
+            &topology,
+            Limits::none(),
+            None,
+            &mut RoundRobin::default(),
+            100,
+        );
+        assert_eq!(run.signal(), Some(300));
+        assert!(run.results.iter().all(|r| r.error.is_none()));
+    }
+
+    #[test]
+    fn test_errors_and_deadlock() {
+        let mut topology = Topology::new();
+        topology
+            .add_machine("lonely", parse_mem_file("3,0,3,0,99"), &[1])
+            .unwrap();
+        let wait = parse_mem_file("3,0,4,0,99");
+        topology.add_machine("A", wait.clone(), &[]).unwrap();
+        topology.add_machine("B", wait, &[]).unwrap();
+        topology.link("A", "B").unwrap();
+        topology.link("B", "A").unwrap();
+
+        let threaded = run_threaded(&topology, Limits::none(), None);
+        let run = run_cooperative(
+            &topology,
+            Limits::none(),
+            None,
+            &mut RoundRobin::default(),
+            10,
+        );
+        assert_eq!(run, threaded);
+        assert_eq!(
+            run.results[0].error,
+            Some(VmError::InputClosed {
+                pc: 2,
+                instruction: 3
+            })
+        );
+        let deadlock = run.deadlock.unwrap();
+        assert_eq!(deadlock.machines.len(), 2);
+        assert_eq!(deadlock.machines[1].sources, &["A"]);
+    }
+
+    // Two machines feeding a third, which echoes six values.
+    fn fan_in() -> Topology {
+        let mut topology = Topology::new();
+        topology
+            .add_machine("P", parse_mem_file("104,1,104,2,104,3,99"), &[])
+            .unwrap();
+        topology
+            .add_machine("Q", parse_mem_file("104,10,104,20,104,30,99"), &[])
+            .unwrap();
+        let echo = format!("{}99", "3,100,4,100,".repeat(6));
+        topology
+            .add_machine("sink", parse_mem_file(&echo), &[])
+            .unwrap();
+        topology.link("P", "sink").unwrap();
+        topology.link("Q", "sink").unwrap();
+        topology
+    }
+
+    fn seeded(topology: &Topology, seed: u64, slice: u64) -> (NetworkRun, Schedule) {
+        let mut policy = Seeded::new(seed);
+        let mut recorder = Recorder::new(&mut policy);
+        let run = run_cooperative(topology, Limits::none(), None, &mut recorder, slice);
+        (run, recorder.schedule)
+    }
+
+    #[test]
+    fn test_seeded() {
+        let topology = amplifiers(
+            include_str!("../testA2.txt"),
+            &[9, 8, 7, 6, 5],
+            Wiring::Feedback,
+        );
+        let threaded = run_threaded(&topology, Limits::none(), None);
+        for seed in 0..5 {
+            assert_eq!(seeded(&topology, seed, 20).0, threaded);
+        }
+
+        let topology = fan_in();
+        assert_eq!(seeded(&topology, 7, 3), seeded(&topology, 7, 3));
+        let orders: Vec<Vec<Base>> = (0..20)
+            .map(|seed| seeded(&topology, seed, 3).0.results[2].outputs.clone())
+            .collect();
+        assert!(orders.iter().any(|order| order != &orders[0]));
+    }
+
+    #[test]
+    fn test_replay() {
+        let topology = fan_in();
+        for seed in 0..10 {
+            let (recorded, schedule) = seeded(&topology, seed, 4);
+            let schedule = Schedule::parse(&schedule.to_text()).unwrap();
+            let mut replay = Replay::new(schedule);
+            let run = run_cooperative(&topology, Limits::none(), None, &mut replay, 4);
+            assert_eq!(run, recorded);
+            assert_eq!(replay.diverged(), None);
+        }
+    }
+
+    #[test]
+    fn test_replay_diverged() {
+        let topology = fan_in();
+        let schedule = Schedule {
+            turns: vec![
+                Turn {
+                    machine: 0,
+                    steps: 1,
+                },
+                Turn {
+                    machine: 5,
+                    steps: 1,
+                },
+            ],
+        };
+        let mut replay = Replay::new(schedule);
+        let run = run_cooperative(&topology, Limits::none(), None, &mut replay, 4);
+        assert_eq!(replay.diverged(), Some(1));
+        assert!(run.results.iter().all(|r| r.error.is_none()));
+    }
+
+    #[test]
+    fn test_schedule_text() {
+        let schedule = Schedule::parse("# machine steps\n2 10\n\n0 1  # first\n").unwrap();
+        assert_eq!(
+            schedule.turns,
+            &[
+                Turn {
+                    machine: 2,
+                    steps: 10
+                },
+                Turn {
+                    machine: 0,
+                    steps: 1
+                }
+            ]
+        );
+        assert_eq!(schedule.to_text(), "# machine steps\n2 10\n0 1\n");
+        assert_eq!(
+            Schedule::parse("0 1\nA 3\n"),
+            Err("line 2: invalid turn `A 3`".to_owned())
+        );
+        assert!(Schedule::parse("0 1 2").is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use intcode::{Base, Computer, Limits, RunStatus, VmError};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// How the amplifiers are connected.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wiring {
+    /// Each amplifier feeds the next and the last one's output is the signal.
+    Serial,
+    /// Like `Serial`, but the last amplifier also feeds the first, until
+    /// they all halt.
+    Feedback,
+}
+
+/// The phase setting that gave the highest thruster signal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Best {
+    pub phases: Vec<Base>,
+    pub signal: Base,
+}
+
+/// A phase setting whose amplifiers failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchError {
+    pub phases: Vec<Base>,
+    /// The amplifier that failed, counting from 0.
+    pub amp: usize,
+    pub error: VmError,
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "phases {}: [{}] {}",
+            format_phases(&self.phases),
+            amp_name(self.amp),
+            self.error
+        )
+    }
+}
+
+/// Names amplifiers `A`, `B`, ... as in the puzzle, then `A26`, ...
+pub fn amp_name(i: usize) -> String {
+    let letter = (b'A' + (i % 26) as u8) as char;
+    if i < 26 {
+        letter.to_string()
+    } else {
+        format!("{}{}", letter, i)
+    }
+}
+
+pub fn format_phases(phases: &[Base]) -> String {
+    let phases: Vec<String> = phases.iter().map(|p| p.to_string()).collect();
+    phases.join(" ")
+}
+
+/// Parses a phase set written as a range, `5-9`, or a list, `0,1,2,3,4`.
+pub fn parse_phase_set(s: &str) -> Result<Vec<Base>, String> {
+    let invalid = || format!("invalid phase set `{}`", s);
+    if let Some(idx) = s.find('-').filter(|&idx| idx > 0) {
+        let lo: Base = s[..idx].trim().parse().map_err(|_| invalid())?;
+        let hi: Base = s[idx + 1..].trim().parse().map_err(|_| invalid())?;
+        if lo > hi {
+            return Err(invalid());
+        }
+        return Ok((lo..=hi).collect());
+    }
+    s.split(',')
+        .map(|p| p.trim().parse().map_err(|_| invalid()))
+        .collect()
+}
+
+/// Runs one amplifier per phase, each a fresh copy of `program`, and
+/// returns the last signal the final amplifier produced, or `None` if it
+/// produced none.
+///
+/// The amplifiers take turns on the calling thread, each running until it
+/// needs input it doesn't have yet. If a whole round passes without any of
+/// them making progress, the first one still waiting fails with
+/// `VmError::InputClosed`.
+pub fn amplify(
+    program: &[Base],
+    phases: &[Base],
+    wiring: Wiring,
+    limits: Limits,
+) -> Result<Option<Base>, (usize, VmError)> {
+    let mut amps: Vec<Computer> = phases
+        .iter()
+        .enumerate()
+        .map(|(i, &phase)| {
+            let mut amp =
+                Computer::new(&amp_name(i), program.to_vec(), VecDeque::new(), Vec::new());
+            amp.set_limits(limits);
+            amp.push_input(phase);
+            amp
+        })
+        .collect();
+    if let Some(first) = amps.first_mut() {
+        first.push_input(0);
+    }
+
+    let last = phases.len().saturating_sub(1);
+    let mut signal = None;
+    loop {
+        let before: u64 = amps.iter().map(|amp| amp.executed()).sum();
+        for i in 0..amps.len() {
+            while let RunStatus::ProducedOutput(value) = amps[i].run().map_err(|err| (i, err))? {
+                if i < last {
+                    amps[i + 1].push_input(value);
+                    continue;
+                }
+                signal = Some(value);
+                if wiring == Wiring::Feedback {
+                    amps[0].push_input(value);
+                }
+            }
+        }
+        if amps.iter().all(|amp| amp.halted) {
+            return Ok(signal);
+        }
+        let after: u64 = amps.iter().map(|amp| amp.executed()).sum();
+        if after == before {
+            let (i, amp) = amps
+                .iter()
+                .enumerate()
+                .find(|(_, amp)| !amp.halted)
+                .unwrap();
+            let instruction = amp.mem().get(amp.pc).unwrap_or(0);
+            return Err((
+                i,
+                VmError::InputClosed {
+                    pc: amp.pc,
+                    instruction,
+                },
+            ));
+        }
+    }
+}
+
+/// Every ordering of `amps` distinct phases drawn from `phase_set`.
+pub fn permutations(phase_set: &[Base], amps: usize) -> Vec<Vec<Base>> {
+    fn extend(
+        phase_set: &[Base],
+        amps: usize,
+        used: &mut Vec<bool>,
+        current: &mut Vec<Base>,
+        out: &mut Vec<Vec<Base>>,
+    ) {
+        if current.len() == amps {
+            out.push(current.clone());
+            return;
+        }
+        for i in 0..phase_set.len() {
+            if !used[i] {
+                used[i] = true;
+                current.push(phase_set[i]);
+                extend(phase_set, amps, used, current, out);
+                current.pop();
+                used[i] = false;
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    if amps <= phase_set.len() {
+        let mut used = vec![false; phase_set.len()];
+        extend(phase_set, amps, &mut used, &mut Vec::new(), &mut out);
+    }
+    out
+}
+
+/// Tries every phase setting of `amps` amplifiers drawn from `phase_set`,
+/// spread over `threads` threads, and returns the one with the highest
+/// signal. Ties go to the setting listed first by `permutations`. Settings
+/// that produce no signal are skipped; one that fails stops the search.
+pub fn search(
+    program: &[Base],
+    phase_set: &[Base],
+    amps: usize,
+    wiring: Wiring,
+    limits: Limits,
+    threads: usize,
+) -> Result<Option<Best>, SearchError> {
+    let settings = permutations(phase_set, amps);
+    let next = AtomicUsize::new(0);
+
+    let results: Vec<Result<Option<(usize, Base)>, SearchError>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut best: Option<(usize, Base)> = None;
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        let phases = match settings.get(idx) {
+                            Some(phases) => phases,
+                            None => return Ok(best),
+                        };
+                        let signal = match amplify(program, phases, wiring, limits) {
+                            Ok(Some(signal)) => signal,
+                            Ok(None) => continue,
+                            Err((amp, error)) => {
+                                // Let the other workers stop early.
+                                next.store(settings.len(), Ordering::Relaxed);
+                                return Err(SearchError {
+                                    phases: phases.clone(),
+                                    amp,
+                                    error,
+                                });
+                            }
+                        };
+                        if best.is_none_or(|(_, b)| signal > b) {
+                            best = Some((idx, signal));
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+
+    let mut best: Option<(usize, Base)> = None;
+    for result in results {
+        if let Some((idx, signal)) = result? {
+            let better = match best {
+                None => true,
+                Some((b_idx, b)) => signal > b || (signal == b && idx < b_idx),
+            };
+            if better {
+                best = Some((idx, signal));
+            }
+        }
+    }
+    Ok(best.map(|(idx, signal)| Best {
+        phases: settings[idx].clone(),
+        signal,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode::parse_mem_file;
+
+    #[test]
+    fn test_parse_phase_set() {
+        assert_eq!(parse_phase_set("5-9"), Ok(vec![5, 6, 7, 8, 9]));
+        assert_eq!(parse_phase_set("0, 2,4"), Ok(vec![0, 2, 4]));
+        assert_eq!(parse_phase_set("-1"), Ok(vec![-1]));
+        assert!(parse_phase_set("9-5").is_err());
+        assert!(parse_phase_set("a,b").is_err());
+    }
+
+    #[test]
+    fn test_permutations() {
+        assert_eq!(permutations(&[0, 1, 2, 3, 4], 5).len(), 120);
+        assert_eq!(
+            permutations(&[1, 2, 3], 2),
+            &[
+                vec![1, 2],
+                vec![1, 3],
+                vec![2, 1],
+                vec![2, 3],
+                vec![3, 1],
+                vec![3, 2]
+            ]
+        );
+        assert!(permutations(&[1, 2], 3).is_empty());
+    }
+
+    #[test]
+    fn test_amplify() {
+        let serial = parse_mem_file(include_str!("../testB.txt"));
+        assert_eq!(
+            amplify(&serial, &[0, 1, 2, 3, 4], Wiring::Serial, Limits::none()),
+            Ok(Some(54321))
+        );
+        let feedback = parse_mem_file(include_str!("../testA2.txt"));
+        assert_eq!(
+            amplify(
+                &feedback,
+                &[9, 8, 7, 6, 5],
+                Wiring::Feedback,
+                Limits::none()
+            ),
+            Ok(Some(139_629_729))
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let serial = parse_mem_file(include_str!("../testB.txt"));
+        let best = search(
+            &serial,
+            &[0, 1, 2, 3, 4],
+            5,
+            Wiring::Serial,
+            Limits::none(),
+            4,
+        );
+        assert_eq!(
+            best,
+            Ok(Some(Best {
+                phases: vec![0, 1, 2, 3, 4],
+                signal: 54321,
+            }))
+        );
+
+        let feedback = parse_mem_file(include_str!("../testB2.txt"));
+        let best = search(
+            &feedback,
+            &[5, 6, 7, 8, 9],
+            5,
+            Wiring::Feedback,
+            Limits::none(),
+            3,
+        );
+        assert_eq!(
+            best,
+            Ok(Some(Best {
+                phases: vec![9, 7, 8, 5, 6],
+                signal: 18216,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_stalled_chain() {
+        // Reads three values, but only ever gets two.
+        let greedy = parse_mem_file("3,0,3,0,3,0,4,0,99");
+        let err = search(&greedy, &[0, 1], 2, Wiring::Serial, Limits::none(), 2).unwrap_err();
+        assert_eq!(err.amp, 0);
+        assert_eq!(
+            err.error,
+            VmError::InputClosed {
+                pc: 4,
+                instruction: 3
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("phases {}: [A] {}", format_phases(&err.phases), err.error)
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Compares execution speed with and without the decoded instruction cache.
+//!
+//! Run with `cargo bench -p intcode`.
+
+use intcode::{Base, Computer};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: Base = 2_000_000;
+const RUNS: usize = 5;
+
+// Counts cell 20 down to zero, tallying in cell 21 how many passes saw a
+// value below 1000. Four instructions per pass.
+fn program() -> Vec<Base> {
+    let mut mem = vec![
+        1001, 20, -1, 20, // n -= 1
+        1007, 20, 1000, 22, // flag = n < 1000
+        1, 22, 21, 21, // acc += flag
+        1005, 20, 0, // loop while n != 0
+        99,
+    ];
+    mem.resize(23, 0);
+    mem[20] = ITERATIONS;
+    mem
+}
+
+fn time_run(cached: bool) -> Duration {
+    let mut best = Duration::from_secs(u64::MAX);
+    for _ in 0..RUNS {
+        let mut cmp = Computer::new("bench", program(), VecDeque::new(), Vec::new());
+        cmp.set_decode_cache(cached);
+
+        let start = Instant::now();
+        cmp.compute().expect("Benchmark program failed.");
+        let elapsed = start.elapsed();
+
+        assert_eq!(cmp.mem().get(21), Ok(1000));
+        if elapsed < best {
+            best = elapsed;
+        }
+    }
+    best
+}
+
+fn main() {
+    let instructions = ITERATIONS as f64 * 4.0;
+    let uncached = time_run(false);
+    let cached = time_run(true);
+
+    for (label, elapsed) in &[("uncached", uncached), ("cached", cached)] {
+        println!(
+            "{:>8}: {:>8.2?} ({:.1} M instructions/s)",
+            label,
+            elapsed,
+            instructions / elapsed.as_secs_f64() / 1e6
+        );
+    }
+    println!(
+        " speedup: {:.2}x",
+        uncached.as_secs_f64() / cached.as_secs_f64()
+    );
+}
@@ -0,0 +1,508 @@
+use crate::instruction::PMode;
+use crate::macros::preprocess;
+use crate::memory::DEFAULT_LIMIT;
+use crate::Base;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// An assembly error, tagged with the 1-based source line it was found on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+// Mnemonic, opcode, operand count and the index of the operand written to.
+const MNEMONICS: [(&str, Base, usize, Option<usize>); 10] = [
+    ("ADD", 1, 3, Some(2)),
+    ("MUL", 2, 3, Some(2)),
+    ("IN", 3, 1, Some(0)),
+    ("OUT", 4, 1, None),
+    ("JT", 5, 2, None),
+    ("JF", 6, 2, None),
+    ("LT", 7, 3, Some(2)),
+    ("EQ", 8, 3, Some(2)),
+    ("ARB", 9, 1, None),
+    ("HLT", 99, 0, None),
+];
+
+/// A sum of numbers and labels, such as `buf+2`.
+#[derive(Clone, Debug)]
+struct Expr(Vec<(Base, Term)>);
+
+#[derive(Clone, Debug)]
+enum Term {
+    Num(Base),
+    Label(String),
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Instruction {
+        opcode: Base,
+        operands: Vec<(PMode, Expr)>,
+    },
+    Data(Vec<Expr>),
+    Zero(usize),
+}
+
+impl Item {
+    fn size(&self) -> usize {
+        match self {
+            Item::Instruction { operands, .. } => operands.len() + 1,
+            Item::Data(values) => values.len(),
+            Item::Zero(count) => *count,
+        }
+    }
+}
+
+/// Assembles Intcode from source text.
+///
+/// Each line holds an optional `label:`, then an instruction or directive,
+/// then an optional `;` comment. Instructions use the disassembler's
+/// mnemonics and operand syntax: `[x]` for position, `#x` for immediate and
+/// `rb+x` for relative mode, where `x` is a number, a label, or a sum of
+/// them such as `buf+1`. Two directives lay out data:
+///
+/// ```text
+/// msg:  DATA "hi", 10     ; one cell per value or character
+/// buf:  ZERO 4            ; four zero cells
+/// ```
+///
+/// Macros are expanded first; see the `macros` module for their syntax and
+/// `stdlib.asm` for the macros every program can use.
+pub fn assemble(source: &str) -> Result<Vec<Base>, AsmError> {
+    assemble_lines(&preprocess(source)?)
+}
+
+/// Assembles lines tagged with the line numbers to report errors against.
+fn assemble_lines(lines: &[(usize, String)]) -> Result<Vec<Base>, AsmError> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut items = Vec::new();
+    let mut addr = 0;
+
+    for (line, text) in lines {
+        let line = *line;
+        let err = |message: String| AsmError { line, message };
+        let mut rest = strip_comment(text).trim();
+        while let Some((label, after)) = split_label(rest) {
+            check_name(label).map_err(err)?;
+            if labels.insert(label.to_owned(), addr).is_some() {
+                return Err(err(format!("label `{}` is already defined", label)));
+            }
+            rest = after.trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        let item = parse_item(rest).map_err(err)?;
+        addr += item.size();
+        if addr > DEFAULT_LIMIT {
+            return Err(err(format!(
+                "program exceeds the memory limit of {}",
+                DEFAULT_LIMIT
+            )));
+        }
+        items.push((line, item));
+    }
+
+    let mut program = Vec::with_capacity(addr);
+    for (line, item) in items {
+        let eval = |expr: &Expr| eval(expr, &labels).map_err(|message| AsmError { line, message });
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let mut word = opcode;
+                let mut scale = 100;
+                for (p_mode, _) in &operands {
+                    word += scale * mode_digit(*p_mode);
+                    scale *= 10;
+                }
+                program.push(word);
+                for (_, expr) in &operands {
+                    program.push(eval(expr)?);
+                }
+            }
+            Item::Data(values) => {
+                for expr in &values {
+                    program.push(eval(expr)?);
+                }
+            }
+            Item::Zero(count) => {
+                program.resize(program.len() + count, 0);
+            }
+        }
+    }
+    Ok(program)
+}
+
+/// Formats a program the way `parse_mem_file` reads it.
+pub fn to_mem_file(program: &[Base]) -> String {
+    program
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn mode_digit(p_mode: PMode) -> Base {
+    match p_mode {
+        PMode::Position => 0,
+        PMode::Immediate => 1,
+        PMode::Relative => 2,
+    }
+}
+
+pub(crate) fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Splits a leading `label:` off a line.
+pub(crate) fn split_label(text: &str) -> Option<(&str, &str)> {
+    let idx = text.find(':')?;
+    let label = text[..idx].trim();
+    if label.is_empty() || label.contains(|c: char| c.is_whitespace() || c == '"') {
+        return None;
+    }
+    Some((label, &text[idx + 1..]))
+}
+
+pub(crate) fn check_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+            // `$` only appears in labels made by macro expansion.
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+        }
+        _ => false,
+    };
+    if !valid || name == "rb" {
+        return Err(format!("invalid label `{}`", name));
+    }
+    Ok(())
+}
+
+fn parse_item(text: &str) -> Result<Item, String> {
+    let (name, args) = match text.find(char::is_whitespace) {
+        Some(idx) => (&text[..idx], text[idx..].trim()),
+        None => (text, ""),
+    };
+    let args = split_args(args)?;
+    let upper = name.to_ascii_uppercase();
+
+    match upper.as_str() {
+        "DATA" => {
+            if args.is_empty() {
+                return Err("DATA needs at least one value".to_owned());
+            }
+            let mut values = Vec::new();
+            for arg in &args {
+                if arg.starts_with('"') {
+                    for c in parse_string(arg)?.chars() {
+                        values.push(Expr(vec![(1, Term::Num(c as Base))]));
+                    }
+                } else {
+                    values.push(parse_expr(arg)?);
+                }
+            }
+            return Ok(Item::Data(values));
+        }
+        "ZERO" => {
+            let count = match args.as_slice() {
+                [count] => count
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid count `{}`", count))?,
+                _ => return Err("usage: ZERO <count>".to_owned()),
+            };
+            if count > DEFAULT_LIMIT {
+                return Err(format!(
+                    "ZERO count {} exceeds the memory limit of {}",
+                    count, DEFAULT_LIMIT
+                ));
+            }
+            return Ok(Item::Zero(count));
+        }
+        _ => {}
+    }
+
+    let &(mnemonic, opcode, arity, write) = MNEMONICS
+        .iter()
+        .find(|(m, ..)| *m == upper)
+        .ok_or_else(|| format!("unknown mnemonic `{}`", name))?;
+    if args.len() != arity {
+        return Err(format!(
+            "{} takes {} operand(s), found {}",
+            mnemonic,
+            arity,
+            args.len()
+        ));
+    }
+    let mut operands = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        let operand = parse_operand(arg)?;
+        if operand.0 == PMode::Immediate && write == Some(i) {
+            return Err(format!(
+                "{} cannot write to immediate operand `{}`",
+                mnemonic, arg
+            ));
+        }
+        operands.push(operand);
+    }
+    Ok(Item::Instruction { opcode, operands })
+}
+
+/// Splits comma-separated arguments, leaving commas inside strings alone.
+pub(crate) fn split_args(text: &str) -> Result<Vec<&str>, String> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                args.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        return Err("unterminated string".to_owned());
+    }
+    args.push(text[start..].trim());
+    if args.iter().any(|arg| arg.is_empty()) {
+        return Err("empty operand".to_owned());
+    }
+    Ok(args)
+}
+
+fn parse_string(arg: &str) -> Result<String, String> {
+    if arg.len() < 2 || !arg.ends_with('"') {
+        return Err(format!("invalid string {}", arg));
+    }
+    let mut out = String::new();
+    let mut chars = arg[1..arg.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('\\') => out.push('\\'),
+            Some('"') => out.push('"'),
+            other => return Err(format!("invalid escape `\\{}`", other.unwrap_or(' '))),
+        }
+    }
+    Ok(out)
+}
+
+fn parse_operand(arg: &str) -> Result<(PMode, Expr), String> {
+    if let Some(inner) = arg.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| format!("missing `]` in `{}`", arg))?;
+        return Ok((PMode::Position, parse_expr(inner)?));
+    }
+    if let Some(value) = arg.strip_prefix('#') {
+        return Ok((PMode::Immediate, parse_expr(value)?));
+    }
+    if let Some(offset) = arg.strip_prefix("rb") {
+        let offset = offset.trim();
+        if offset.is_empty() {
+            return Ok((PMode::Relative, Expr(vec![(1, Term::Num(0))])));
+        }
+        if offset.starts_with('+') || offset.starts_with('-') {
+            return Ok((PMode::Relative, parse_expr(offset)?));
+        }
+    }
+    Err(format!(
+        "operand `{}` needs a mode: `[x]`, `#x` or `rb+x`",
+        arg
+    ))
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    let mut token = String::new();
+    let mut push = |token: &mut String, sign: Base| -> Result<(), String> {
+        let t = token.trim();
+        if t.is_empty() {
+            return Err(format!("invalid expression `{}`", text));
+        }
+        // Numbers take their sign when parsed, so that the most negative
+        // value can be written.
+        let number = if sign < 0 {
+            format!("-{}", t).parse::<Base>()
+        } else {
+            t.parse::<Base>()
+        };
+        match number {
+            Ok(n) => terms.push((1, Term::Num(n))),
+            Err(_) => {
+                check_name(t).map_err(|_| format!("invalid expression `{}`", text))?;
+                terms.push((sign, Term::Label(t.to_owned())));
+            }
+        }
+        token.clear();
+        Ok(())
+    };
+
+    for (i, c) in text.trim().char_indices() {
+        match c {
+            '+' | '-' if i == 0 => sign = if c == '-' { -1 } else { 1 },
+            '+' | '-' => {
+                push(&mut token, sign)?;
+                sign = if c == '-' { -1 } else { 1 };
+            }
+            _ => token.push(c),
+        }
+    }
+    push(&mut token, sign)?;
+    Ok(Expr(terms))
+}
+
+fn eval(expr: &Expr, labels: &HashMap<String, usize>) -> Result<Base, String> {
+    let mut total: Base = 0;
+    for (sign, term) in &expr.0 {
+        let value = match term {
+            Term::Num(n) => *n,
+            Term::Label(name) => *labels
+                .get(name)
+                .ok_or_else(|| format!("undefined label `{}`", name))?
+                as Base,
+        };
+        total = total.wrapping_add(sign.wrapping_mul(value));
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Computer;
+    use crate::disasm::disassemble;
+    use crate::parse_mem_file;
+    use std::collections::VecDeque;
+
+    fn error(source: &str) -> AsmError {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn test_encodes_modes() {
+        assert_eq!(
+            assemble("ADD [5], #3, rb-2\nHLT").unwrap(),
+            &[21001, 5, 3, -2, 99]
+        );
+    }
+
+    #[test]
+    fn test_extreme_values() {
+        assert_eq!(
+            assemble("DATA -9223372036854775808, 9223372036854775807+1, x-1\nx: HLT").unwrap(),
+            &[Base::MIN, Base::MIN, 2, 99]
+        );
+    }
+
+    #[test]
+    fn test_labels_and_data() {
+        let source = "
+            ; Counts down from the value at `n`.
+            loop:   OUT [n]
+                    ADD [n], #-1, [n]
+                    JT [n], #loop
+                    HLT
+            n:      DATA 3
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program[..4], [4, 10, 1001, 10]);
+
+        let mut cmp = Computer::new("asm", program, VecDeque::new(), Vec::new());
+        cmp.compute().unwrap();
+        assert_eq!(cmp.output(), &[3, 2, 1]);
+    }
+
+    #[test]
+    fn test_strings_and_zero() {
+        let program =
+            assemble("msg: DATA \"a,b\\n\", 0 ; trailing\nbuf: ZERO 2\nend: DATA end-msg").unwrap();
+        assert_eq!(program, &[97, 44, 98, 10, 0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn test_round_trips_disassembly() {
+        // The second feedback-loop example from day 7.
+        let program = parse_mem_file(
+            "3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,\
+             -5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,\
+             53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10",
+        );
+        let source: Vec<String> = disassemble(&program)
+            .iter()
+            .map(|line| line.text.clone())
+            .collect();
+        assert_eq!(assemble(&source.join("\n")).unwrap(), program);
+        assert_eq!(parse_mem_file(&to_mem_file(&program)), program);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error("HLT\nFOO #1").to_string(),
+            "line 2: unknown mnemonic `FOO`"
+        );
+        assert_eq!(
+            error("IN #4").message,
+            "IN cannot write to immediate operand `#4`"
+        );
+        assert_eq!(error("\n\nOUT 4").line, 3);
+        assert_eq!(
+            error("ADD [1], [2]").message,
+            "ADD takes 3 operand(s), found 2"
+        );
+        assert_eq!(
+            error("JT #1, #nowhere").message,
+            "undefined label `nowhere`"
+        );
+        assert_eq!(
+            error("a: HLT\na: HLT").to_string(),
+            "line 2: label `a` is already defined"
+        );
+        assert_eq!(
+            error("HLT\nZERO 100000000000").to_string(),
+            format!(
+                "line 2: ZERO count 100000000000 exceeds the memory limit of {}",
+                DEFAULT_LIMIT
+            )
+        );
+        assert_eq!(
+            error("ZERO 16777216\nZERO 1").to_string(),
+            format!(
+                "line 2: program exceeds the memory limit of {}",
+                DEFAULT_LIMIT
+            )
+        );
+    }
+}
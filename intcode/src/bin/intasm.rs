@@ -0,0 +1,26 @@
+//! Assembles an Intcode program and prints it in the comma-separated form
+//! read by `parse_mem_file`.
+//!
+//! Usage: `intasm <source file>`.
+
+use intcode::{assemble, to_mem_file};
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("Must supply assembly file.");
+    }
+
+    let filename = &args[1];
+    let source = fs::read_to_string(filename).expect("Failed to read file.");
+    match assemble(&source) {
+        Ok(program) => println!("{}", to_mem_file(&program)),
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
+            process::exit(1);
+        }
+    }
+}
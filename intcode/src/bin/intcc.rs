@@ -0,0 +1,34 @@
+//! Compiles a program in the small language described in the `compiler`
+//! module and prints it in the comma-separated form read by `parse_mem_file`.
+//!
+//! Usage: `intcc [--asm] <source file>`. With `--asm` the generated
+//! assembly is printed instead.
+
+use intcode::{compile, compile_to_asm, to_mem_file};
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let asm = args.iter().any(|arg| arg == "--asm");
+    args.retain(|arg| arg != "--asm");
+    if args.is_empty() {
+        panic!("Must supply source file.");
+    }
+
+    let filename = &args[0];
+    let source = fs::read_to_string(filename).expect("Failed to read file.");
+    let result = if asm {
+        compile_to_asm(&source)
+    } else {
+        compile(&source).map(|program| to_mem_file(&program))
+    };
+    match result {
+        Ok(text) => println!("{}", text),
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
+            process::exit(1);
+        }
+    }
+}
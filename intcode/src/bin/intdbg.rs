@@ -0,0 +1,46 @@
+//! An interactive debugger for Intcode programs.
+//!
+//! Usage: `intdbg <program file>`. Type `help` at the prompt for commands.
+
+use intcode::{parse_mem_file, Computer, Debugger, Reply};
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("Must supply intcode file.");
+    }
+
+    let filename = &args[1];
+    let input = fs::read_to_string(filename).expect("Failed to read file.");
+    let cmp = Computer::new(
+        filename,
+        parse_mem_file(&input),
+        VecDeque::new(),
+        Vec::new(),
+    );
+    let mut dbg = Debugger::new(cmp);
+    println!("{}", dbg.location());
+
+    loop {
+        print!("(intdbg) ");
+        io::stdout().flush().expect("Error during flush.");
+        let mut line = String::new();
+        let read = io::stdin()
+            .read_line(&mut line)
+            .expect("Failed to read input");
+        if read == 0 {
+            break;
+        }
+
+        match dbg.execute(&line) {
+            Reply::Text(text) if text.is_empty() => {}
+            Reply::Text(text) => println!("{}", text),
+            Reply::Quit => break,
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Compares two execution traces and reports the first record where they
+//! differ.
+//!
+//! Usage: `intdiff [--context N] <trace file> <trace file>`. Either trace
+//! format is accepted. Exits with status 1 if the traces differ.
+
+use intcode::{divergence_report, first_divergence, Trace};
+use std::env;
+use std::process;
+
+fn main() {
+    let mut context = 3;
+    let mut files = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let value = args.next().expect("Missing value for --context.");
+                context = value.parse().expect("Invalid value for --context.");
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.len() < 2 {
+        panic!("Must supply two trace files.");
+    }
+
+    let load = |path: &String| {
+        Trace::load(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(2);
+        })
+    };
+    let a = load(&files[0]);
+    let b = load(&files[1]);
+    match first_divergence(&a.records, &b.records) {
+        Some(divergence) => {
+            print!("{}", divergence_report(&a, &b, &divergence, context));
+            process::exit(1);
+        }
+        None => println!("traces match ({} records)", a.records.len()),
+    }
+}
@@ -0,0 +1,19 @@
+//! Prints a disassembly listing of an Intcode program.
+//!
+//! Usage: `intdis <program file>`.
+
+use intcode::{disassemble, parse_mem_file};
+use std::env;
+use std::fs;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("Must supply intcode file.");
+    }
+
+    let input = fs::read_to_string(&args[1]).expect("Failed to read file.");
+    for line in disassemble(&parse_mem_file(&input)) {
+        println!("{}", line);
+    }
+}
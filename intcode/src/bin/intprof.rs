@@ -0,0 +1,42 @@
+//! Runs an Intcode program with profiling on and prints a hot-spot report
+//! when it halts.
+//!
+//! Usage: `intprof [--top N] <program file>`. Input is read from stdin and
+//! the report goes to stderr, so it does not mix with the program's output.
+
+use intcode::{parse_mem_file, Computer, StdinInput, StdoutOutput};
+use std::env;
+use std::fs;
+
+fn main() {
+    let mut top = 20;
+    let mut files = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--top" => {
+                let value = args.next().expect("Missing value for --top.");
+                top = value.parse().expect("Invalid value for --top.");
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        panic!("Must supply intcode file.");
+    }
+
+    let input = fs::read_to_string(&files[0]).expect("Failed to read file.");
+    let mut cmp = Computer::new(
+        &files[0],
+        parse_mem_file(&input),
+        StdinInput::new("INPUT: "),
+        StdoutOutput::new("OUTPUT: "),
+    );
+    cmp.set_profiling(true);
+
+    if let Err(err) = cmp.compute() {
+        eprintln!("{}", err);
+    }
+    let profile = cmp.profile().expect("Profiling is on.");
+    eprint!("{}", profile.report(cmp.mem(), top));
+}
@@ -0,0 +1,108 @@
+use crate::instruction::OpCode;
+use crate::memory::PAGE_SIZE;
+use crate::Base;
+
+/// The longest instruction, in cells.
+const MAX_LEN: usize = 4;
+
+/// An instruction decoded from memory, along with the word it came from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Decoded {
+    pub(crate) word: Base,
+    pub(crate) op: OpCode,
+}
+
+/// Decoded instructions keyed by address, stored in pages like `Memory` so
+/// that code at a high address doesn't allocate entries for every address
+/// below it.
+///
+/// The owner must call `invalidate` for every memory write so that
+/// self-modifying programs never execute a stale decode.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InstructionCache {
+    // `None` pages hold no entries.
+    pages: Vec<Option<Box<[Option<Decoded>]>>>,
+}
+
+impl InstructionCache {
+    pub(crate) fn get(&self, pc: usize) -> Option<Decoded> {
+        match self.pages.get(pc / PAGE_SIZE) {
+            Some(Some(page)) => page[pc % PAGE_SIZE],
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, pc: usize, decoded: Decoded) {
+        let page_idx = pc / PAGE_SIZE;
+        if page_idx >= self.pages.len() {
+            self.pages.resize(page_idx + 1, None);
+        }
+        let page =
+            self.pages[page_idx].get_or_insert_with(|| vec![None; PAGE_SIZE].into_boxed_slice());
+        page[pc % PAGE_SIZE] = Some(decoded);
+    }
+
+    /// Drops every entry whose instruction covers `addr`.
+    pub(crate) fn invalidate(&mut self, addr: usize) {
+        for start in addr.saturating_sub(MAX_LEN - 1)..=addr {
+            if let Some(decoded) = self.get(start) {
+                if start + decoded.op.size() > addr {
+                    if let Some(Some(page)) = self.pages.get_mut(start / PAGE_SIZE) {
+                        page[start % PAGE_SIZE] = None;
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::parse_instruction;
+
+    fn decoded(word: Base) -> Decoded {
+        Decoded {
+            word,
+            op: parse_instruction(word).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_invalidate_covers_operands() {
+        let mut cache = InstructionCache::default();
+        cache.insert(0, decoded(1));
+        cache.insert(4, decoded(104));
+        cache.insert(6, decoded(99));
+
+        cache.invalidate(3);
+        assert_eq!(cache.get(0), None);
+        assert_eq!(cache.get(4), Some(decoded(104)));
+
+        cache.invalidate(6);
+        assert_eq!(cache.get(4), Some(decoded(104)));
+        assert_eq!(cache.get(6), None);
+
+        cache.invalidate(5);
+        assert_eq!(cache.get(4), None);
+    }
+
+    #[test]
+    fn test_high_address() {
+        let mut cache = InstructionCache::default();
+        let pc = 16_000_000;
+        cache.insert(pc, decoded(99));
+        assert_eq!(cache.get(pc), Some(decoded(99)));
+        assert_eq!(cache.get(pc - 1), None);
+        assert_eq!(cache.pages.iter().filter(|page| page.is_some()).count(), 1);
+
+        // An instruction that straddles a page boundary.
+        cache.insert(PAGE_SIZE - 2, decoded(1));
+        cache.invalidate(PAGE_SIZE);
+        assert_eq!(cache.get(PAGE_SIZE - 2), None);
+    }
+}
@@ -0,0 +1,365 @@
+use super::parser::{BinOp, Expr, Function, Stmt, UnOp};
+use super::CompileError;
+use crate::Base;
+use std::collections::HashMap;
+
+// Replaced by the frame size once a function has been compiled.
+const FRAME: &str = "%FRAME";
+
+/// Where an expression's value lives.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Operand {
+    Imm(Base),
+    /// A cell in the current frame, at `rb` plus this offset.
+    Slot(usize),
+}
+
+impl Operand {
+    fn text(self) -> String {
+        match self {
+            Operand::Imm(n) => format!("#{}", n),
+            Operand::Slot(slot) => format!("rb+{}", slot),
+        }
+    }
+}
+
+/// Generates assembly for a whole program.
+///
+/// Each call gets a frame addressed through `rb`: the return address at
+/// `rb+0`, then the parameters, then locals and temporaries. A caller places
+/// the callee's frame just past its own, so it moves `rb` forward by its
+/// frame size before the call and back afterwards. Return values travel
+/// through the `__ret` cell.
+pub(crate) fn generate(functions: &[Function]) -> Result<String, CompileError> {
+    let mut arities = HashMap::new();
+    for f in functions {
+        if f.name == "input" || f.name == "output" {
+            return Err(CompileError {
+                line: f.line,
+                message: format!("`{}` is a builtin", f.name),
+            });
+        }
+        if arities.insert(f.name.clone(), f.params.len()).is_some() {
+            return Err(CompileError {
+                line: f.line,
+                message: format!("function `{}` is already defined", f.name),
+            });
+        }
+    }
+    match arities.get("main") {
+        Some(0) => {}
+        Some(_) => {
+            let line = functions.iter().find(|f| f.name == "main").unwrap().line;
+            return Err(CompileError {
+                line,
+                message: "`main` cannot take parameters".to_owned(),
+            });
+        }
+        None => {
+            return Err(CompileError {
+                line: 1,
+                message: "no `main` function".to_owned(),
+            })
+        }
+    }
+
+    let mut out = vec![
+        "        ARB #__stack".to_owned(),
+        "        ADD #__halt, #0, rb+0".to_owned(),
+        "        JT #1, #fn_main".to_owned(),
+        "__halt: HLT".to_owned(),
+        "__ret:  DATA 0".to_owned(),
+    ];
+    let mut labels = 0;
+    for f in functions {
+        let mut gen = FnGen {
+            arities: &arities,
+            labels: &mut labels,
+            scopes: vec![HashMap::new()],
+            next_slot: 1,
+            max_slot: 1,
+            code: Vec::new(),
+        };
+        for (i, param) in f.params.iter().enumerate() {
+            gen.scopes[0].insert(param.clone(), i + 1);
+        }
+        gen.next_slot += f.params.len();
+        gen.max_slot = gen.next_slot;
+
+        gen.code.push(format!("fn_{}:", f.name));
+        gen.block(&f.body)?;
+        gen.emit("ADD #0, #0, [__ret]");
+        gen.emit("JT #1, rb+0");
+
+        let frame = gen.max_slot.to_string();
+        out.extend(gen.code.iter().map(|line| line.replace(FRAME, &frame)));
+    }
+    out.push("__stack: DATA 0".to_owned());
+    Ok(out.join("\n"))
+}
+
+struct FnGen<'a> {
+    arities: &'a HashMap<String, usize>,
+    labels: &'a mut usize,
+    scopes: Vec<HashMap<String, usize>>,
+    next_slot: usize,
+    max_slot: usize,
+    code: Vec<String>,
+}
+
+impl<'a> FnGen<'a> {
+    fn emit(&mut self, instruction: &str) {
+        self.code.push(format!("        {}", instruction));
+    }
+
+    fn label(&mut self) -> String {
+        *self.labels += 1;
+        format!("L{}", self.labels)
+    }
+
+    fn place(&mut self, label: &str) {
+        self.code.push(format!("{}:", label));
+    }
+
+    fn alloc(&mut self) -> usize {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.max_slot = self.max_slot.max(self.next_slot);
+        slot
+    }
+
+    fn lookup(&self, name: &str, line: usize) -> Result<usize, CompileError> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+            .ok_or_else(|| CompileError {
+                line,
+                message: format!("undefined variable `{}`", name),
+            })
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), CompileError> {
+        let mark = self.next_slot;
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        self.scopes.pop();
+        self.next_slot = mark;
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        // Temporaries only live for one statement.
+        let mark = self.next_slot;
+        match stmt {
+            Stmt::Let(name, value) => {
+                let slot = self.alloc();
+                let value = self.expr(value)?;
+                self.emit(&format!("ADD {}, #0, rb+{}", value.text(), slot));
+                self.scopes.last_mut().unwrap().insert(name.clone(), slot);
+                self.next_slot = slot + 1;
+                return Ok(());
+            }
+            Stmt::Assign(name, value, line) => {
+                let slot = self.lookup(name, *line)?;
+                let value = self.expr(value)?;
+                self.emit(&format!("ADD {}, #0, rb+{}", value.text(), slot));
+            }
+            Stmt::If(cond, then, els) => {
+                let else_label = self.label();
+                let end_label = self.label();
+                let cond = self.expr(cond)?;
+                self.next_slot = mark;
+                self.emit(&format!("JF {}, #{}", cond.text(), else_label));
+                self.block(then)?;
+                self.emit(&format!("JT #1, #{}", end_label));
+                self.place(&else_label);
+                self.block(els)?;
+                self.place(&end_label);
+            }
+            Stmt::While(cond, body) => {
+                let top_label = self.label();
+                let end_label = self.label();
+                self.place(&top_label);
+                let cond = self.expr(cond)?;
+                self.next_slot = mark;
+                self.emit(&format!("JF {}, #{}", cond.text(), end_label));
+                self.block(body)?;
+                self.emit(&format!("JT #1, #{}", top_label));
+                self.place(&end_label);
+            }
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value)?,
+                    None => Operand::Imm(0),
+                };
+                self.emit(&format!("ADD {}, #0, [__ret]", value.text()));
+                self.emit("JT #1, rb+0");
+            }
+            Stmt::Expr(value) => {
+                self.expr(value)?;
+            }
+        }
+        self.next_slot = mark;
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Operand, CompileError> {
+        match expr {
+            Expr::Num(n) => Ok(Operand::Imm(*n)),
+            Expr::Var(name, line) => Ok(Operand::Slot(self.lookup(name, *line)?)),
+            Expr::Unary(op, inner) => {
+                let inner = self.expr(inner)?;
+                if let Operand::Imm(n) = inner {
+                    return Ok(Operand::Imm(match op {
+                        UnOp::Neg => n.wrapping_neg(),
+                        UnOp::Not => (n == 0) as Base,
+                    }));
+                }
+                let t = self.alloc();
+                match op {
+                    UnOp::Neg => self.emit(&format!("MUL {}, #-1, rb+{}", inner.text(), t)),
+                    UnOp::Not => self.emit(&format!("EQ {}, #0, rb+{}", inner.text(), t)),
+                }
+                Ok(Operand::Slot(t))
+            }
+            Expr::Binary(op @ BinOp::And, lhs, rhs) | Expr::Binary(op @ BinOp::Or, lhs, rhs) => {
+                self.short_circuit(*op, lhs, rhs)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let a = self.expr(lhs)?;
+                let b = self.expr(rhs)?;
+                if let (Operand::Imm(a), Operand::Imm(b)) = (a, b) {
+                    return Ok(Operand::Imm(fold(*op, a, b)));
+                }
+                let t = self.alloc();
+                let (a, b, out) = (a.text(), b.text(), format!("rb+{}", t));
+                match op {
+                    BinOp::Add => self.emit(&format!("ADD {}, {}, {}", a, b, out)),
+                    BinOp::Sub => {
+                        self.emit(&format!("MUL {}, #-1, {}", b, out));
+                        self.emit(&format!("ADD {}, {}, {}", a, out, out));
+                    }
+                    BinOp::Mul => self.emit(&format!("MUL {}, {}, {}", a, b, out)),
+                    BinOp::Less => self.emit(&format!("LT {}, {}, {}", a, b, out)),
+                    BinOp::Greater => self.emit(&format!("LT {}, {}, {}", b, a, out)),
+                    BinOp::LessEq => {
+                        self.emit(&format!("LT {}, {}, {}", b, a, out));
+                        self.emit(&format!("EQ {}, #0, {}", out, out));
+                    }
+                    BinOp::GreaterEq => {
+                        self.emit(&format!("LT {}, {}, {}", a, b, out));
+                        self.emit(&format!("EQ {}, #0, {}", out, out));
+                    }
+                    BinOp::Eq => self.emit(&format!("EQ {}, {}, {}", a, b, out)),
+                    BinOp::NotEq => {
+                        self.emit(&format!("EQ {}, {}, {}", a, b, out));
+                        self.emit(&format!("EQ {}, #0, {}", out, out));
+                    }
+                    BinOp::And | BinOp::Or => unreachable!(),
+                }
+                Ok(Operand::Slot(t))
+            }
+            Expr::Call(name, args, line) => self.call(name, args, *line),
+        }
+    }
+
+    /// `&&` and `||` skip their right-hand side when the left decides the
+    /// result.
+    fn short_circuit(
+        &mut self,
+        op: BinOp,
+        lhs: &Expr,
+        rhs: &Expr,
+    ) -> Result<Operand, CompileError> {
+        let (jump, skipped) = match op {
+            BinOp::And => ("JF", 0),
+            _ => ("JT", 1),
+        };
+        let t = self.alloc();
+        let end_label = self.label();
+        self.emit(&format!("ADD #{}, #0, rb+{}", skipped, t));
+        let a = self.expr(lhs)?;
+        self.emit(&format!("{} {}, #{}", jump, a.text(), end_label));
+        let b = self.expr(rhs)?;
+        self.emit(&format!("{} {}, #{}", jump, b.text(), end_label));
+        self.emit(&format!("ADD #{}, #0, rb+{}", 1 - skipped, t));
+        self.place(&end_label);
+        Ok(Operand::Slot(t))
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], line: usize) -> Result<Operand, CompileError> {
+        let arity = match name {
+            "input" => 0,
+            "output" => 1,
+            _ => *self.arities.get(name).ok_or_else(|| CompileError {
+                line,
+                message: format!("undefined function `{}`", name),
+            })?,
+        };
+        if args.len() != arity {
+            return Err(CompileError {
+                line,
+                message: format!(
+                    "`{}` takes {} argument(s), found {}",
+                    name,
+                    arity,
+                    args.len()
+                ),
+            });
+        }
+
+        match name {
+            "input" => {
+                let t = self.alloc();
+                self.emit(&format!("IN rb+{}", t));
+                return Ok(Operand::Slot(t));
+            }
+            "output" => {
+                let value = self.expr(&args[0])?;
+                self.emit(&format!("OUT {}", value.text()));
+                return Ok(Operand::Imm(0));
+            }
+            _ => {}
+        }
+
+        // Evaluate every argument before filling in the callee's frame, as
+        // nested calls use the same space.
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.expr(arg)?);
+        }
+        for (i, value) in values.iter().enumerate() {
+            self.emit(&format!("ADD {}, #0, rb+{}+{}", value.text(), FRAME, i + 1));
+        }
+        let ret_label = self.label();
+        self.emit(&format!("ADD #{}, #0, rb+{}", ret_label, FRAME));
+        self.emit(&format!("ARB #{}", FRAME));
+        self.emit(&format!("JT #1, #fn_{}", name));
+        self.place(&ret_label);
+        self.emit(&format!("ARB #-{}", FRAME));
+        let t = self.alloc();
+        self.emit(&format!("ADD [__ret], #0, rb+{}", t));
+        Ok(Operand::Slot(t))
+    }
+}
+
+/// Evaluates `a op b` at compile time, wrapping on overflow as the machine
+/// does.
+fn fold(op: BinOp, a: Base, b: Base) -> Base {
+    match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::Less => (a < b) as Base,
+        BinOp::Greater => (a > b) as Base,
+        BinOp::LessEq => (a <= b) as Base,
+        BinOp::GreaterEq => (a >= b) as Base,
+        BinOp::Eq => (a == b) as Base,
+        BinOp::NotEq => (a != b) as Base,
+        BinOp::And => (a != 0 && b != 0) as Base,
+        BinOp::Or => (a != 0 || b != 0) as Base,
+    }
+}
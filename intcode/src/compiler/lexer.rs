@@ -0,0 +1,113 @@
+use super::CompileError;
+use crate::Base;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Tok {
+    Num(Base),
+    Ident(String),
+    Fn,
+    Let,
+    If,
+    Else,
+    While,
+    Return,
+    Sym(&'static str),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Token {
+    pub(crate) tok: Tok,
+    pub(crate) line: usize,
+}
+
+// Longest first, so that `<=` is not read as `<` then `=`.
+const SYMBOLS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "<", ">", "=", "!", "(", ")", "{", "}", ",",
+    ";",
+];
+
+pub(crate) fn lex(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut tokens = Vec::new();
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let code = match text.find("//") {
+            Some(idx) => &text[..idx],
+            None => text,
+        };
+        let mut rest = code.trim_start();
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+            let len = if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let value = rest[..len].parse().map_err(|_| CompileError {
+                    line,
+                    message: format!("number `{}` is too large", &rest[..len]),
+                })?;
+                tokens.push(Token {
+                    tok: Tok::Num(value),
+                    line,
+                });
+                len
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                let tok = match &rest[..len] {
+                    "fn" => Tok::Fn,
+                    "let" => Tok::Let,
+                    "if" => Tok::If,
+                    "else" => Tok::Else,
+                    "while" => Tok::While,
+                    "return" => Tok::Return,
+                    word => Tok::Ident(word.to_owned()),
+                };
+                tokens.push(Token { tok, line });
+                len
+            } else {
+                let sym = SYMBOLS
+                    .iter()
+                    .find(|sym| rest.starts_with(*sym))
+                    .ok_or_else(|| CompileError {
+                        line,
+                        message: format!("unexpected character `{}`", c),
+                    })?;
+                tokens.push(Token {
+                    tok: Tok::Sym(sym),
+                    line,
+                });
+                sym.len()
+            };
+            rest = rest[len..].trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lex() {
+        let toks: Vec<Tok> = lex("let x = 10; // ten\nx<=y")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.tok)
+            .collect();
+        assert_eq!(
+            toks,
+            &[
+                Tok::Let,
+                Tok::Ident("x".to_owned()),
+                Tok::Sym("="),
+                Tok::Num(10),
+                Tok::Sym(";"),
+                Tok::Ident("x".to_owned()),
+                Tok::Sym("<="),
+                Tok::Ident("y".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! A compiler from a small structured language to Intcode.
+//!
+//! A program is a list of functions, and runs by calling `main`:
+//!
+//! ```text
+//! // Outputs the first n squares.
+//! fn main() {
+//!     let n = input();
+//!     let i = 1;
+//!     while i <= n {
+//!         output(square(i));
+//!         i = i + 1;
+//!     }
+//! }
+//!
+//! fn square(x) {
+//!     return x * x;
+//! }
+//! ```
+//!
+//! Every value is a `Base`. Expressions support `+ - *`, comparisons,
+//! `&& || !` and unary minus; comparisons and logic produce 0 or 1, and
+//! any non-zero value counts as true. Statements are `let`, assignment,
+//! `if`/`else`, `while`, `return` and expression statements. `input()`
+//! reads a value and `output(x)` writes one. Functions may be recursive.
+//!
+//! The compiler emits assembly for `assemble`, so `compile_to_asm` can show
+//! what a program turns into.
+
+mod codegen;
+mod lexer;
+mod parser;
+
+use crate::asm::assemble;
+use crate::Base;
+use std::error::Error;
+use std::fmt;
+
+/// A compile error, tagged with the 1-based source line it was found on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CompileError {}
+
+/// Compiles a program to assembly source.
+pub fn compile_to_asm(source: &str) -> Result<String, CompileError> {
+    let tokens = lexer::lex(source)?;
+    let functions = parser::parse(&tokens)?;
+    codegen::generate(&functions)
+}
+
+/// Compiles a program to Intcode.
+pub fn compile(source: &str) -> Result<Vec<Base>, CompileError> {
+    let asm = compile_to_asm(source)?;
+    assemble(&asm).map_err(|err| CompileError {
+        line: 1,
+        message: format!("generated invalid assembly: {}", err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Computer;
+    use std::collections::VecDeque;
+
+    fn run(source: &str, input: &[Base]) -> Vec<Base> {
+        let program = compile(source).unwrap();
+        let mut cmp = Computer::new(
+            "compiled",
+            program,
+            input.iter().copied().collect::<VecDeque<_>>(),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+        cmp.output().clone()
+    }
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let source = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a + b * 2 - 1);
+                output(-a);
+                output(a - b - 1);
+                output(2 * 3 + 1);
+            }
+        ";
+        assert_eq!(run(source, &[7, 5]), &[16, -7, 1, 7]);
+    }
+
+    #[test]
+    fn test_overflow_wraps() {
+        let source = "
+            fn main() {
+                let a = input();
+                output(a + 1);
+                output(9223372036854775807 + 1);
+                output(-(-9223372036854775807 - 1));
+                output(4611686018427387904 * 2);
+            }
+        ";
+        let min = Base::MIN;
+        assert_eq!(run(source, &[Base::MAX]), &[min, min, min, min]);
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let source = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a < b);
+                output(a > b);
+                output(a <= b);
+                output(a >= b);
+                output(a == b);
+                output(a != b);
+                output(a < b && b < 10);
+                output(a > b || b == 4);
+                output(!a);
+            }
+        ";
+        assert_eq!(run(source, &[3, 4]), &[1, 0, 1, 0, 0, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_if_and_while() {
+        let source = "
+            fn main() {
+                let i = 0;
+                while i < 6 {
+                    if i == 1 {
+                        output(100);
+                    } else if i < 3 {
+                        output(200);
+                    } else {
+                        let j = i * 10;
+                        output(j);
+                    }
+                    i = i + 1;
+                }
+            }
+        ";
+        assert_eq!(run(source, &[]), &[200, 100, 200, 30, 40, 50]);
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "
+            fn main() {
+                output(fib(input()));
+                output(add3(1, 20, 300));
+            }
+
+            fn fib(n) {
+                if n < 2 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn add3(a, b, c) {
+                return a + b + c;
+            }
+        ";
+        assert_eq!(run(source, &[15]), &[610, 321]);
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let source = "
+            fn main() {
+                if 0 && side_effect() { }
+                if 1 || side_effect() { }
+                output(1 && side_effect());
+            }
+
+            fn side_effect() {
+                output(99);
+                return 1;
+            }
+        ";
+        assert_eq!(run(source, &[]), &[99, 1]);
+    }
+
+    #[test]
+    fn test_amplifier_chain() {
+        // A day7 style amplifier: reads its phase, then adds it to each
+        // signal it passes on.
+        let source = "
+            fn main() {
+                let phase = input();
+                output(input() * 2 + phase);
+            }
+        ";
+        let program = compile(source).unwrap();
+        let mut signal = 0;
+        for phase in 0..5 {
+            let mut amp = Computer::new(
+                "amp",
+                program.clone(),
+                VecDeque::from(vec![phase, signal]),
+                Vec::new(),
+            );
+            amp.compute().unwrap();
+            signal = amp.output()[0];
+        }
+        assert_eq!(signal, 26);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error("fn main() {\n  x = 1;\n}"),
+            "line 2: undefined variable `x`"
+        );
+        assert_eq!(error("fn f() {}"), "line 1: no `main` function");
+        assert_eq!(
+            error("fn main() {\n  f(1);\n}\nfn f() {}"),
+            "line 2: `f` takes 0 argument(s), found 1"
+        );
+        assert_eq!(
+            error("fn main() {\n  output(1)\n}"),
+            "line 3: expected `;`, found `}`"
+        );
+        assert_eq!(
+            error("fn main() { let x = 1 / 2; }"),
+            "line 1: unexpected character `/`"
+        );
+        assert_eq!(
+            error("fn main() { }\nfn main() { }"),
+            "line 2: function `main` is already defined"
+        );
+    }
+}
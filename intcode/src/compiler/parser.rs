@@ -0,0 +1,352 @@
+use super::lexer::{Tok, Token};
+use super::CompileError;
+use crate::Base;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Less,
+    Greater,
+    LessEq,
+    GreaterEq,
+    Eq,
+    NotEq,
+    And,
+    Or,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Expr {
+    Num(Base),
+    Var(String, usize),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// A call to a function or to the `input` and `output` builtins.
+    Call(String, Vec<Expr>, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr, usize),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Function {
+    pub(crate) name: String,
+    pub(crate) params: Vec<String>,
+    pub(crate) body: Vec<Stmt>,
+    pub(crate) line: usize,
+}
+
+// Binary operators by precedence, loosest first.
+const LEVELS: [&[(&str, BinOp)]; 5] = [
+    &[("||", BinOp::Or)],
+    &[("&&", BinOp::And)],
+    &[
+        ("==", BinOp::Eq),
+        ("!=", BinOp::NotEq),
+        ("<", BinOp::Less),
+        (">", BinOp::Greater),
+        ("<=", BinOp::LessEq),
+        (">=", BinOp::GreaterEq),
+    ],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)],
+    &[("*", BinOp::Mul)],
+];
+
+pub(crate) fn parse(tokens: &[Token]) -> Result<Vec<Function>, CompileError> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut functions = Vec::new();
+    while parser.peek().is_some() {
+        functions.push(parser.function()?);
+    }
+    Ok(functions)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Tok> {
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some(token) => token.line,
+            None => 1,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, CompileError> {
+        Err(CompileError {
+            line: self.line(),
+            message,
+        })
+    }
+
+    fn describe(&self) -> String {
+        match self.peek() {
+            Some(Tok::Num(n)) => format!("`{}`", n),
+            Some(Tok::Ident(name)) => format!("`{}`", name),
+            Some(Tok::Sym(sym)) => format!("`{}`", sym),
+            Some(Tok::Fn) => "`fn`".to_owned(),
+            Some(Tok::Let) => "`let`".to_owned(),
+            Some(Tok::If) => "`if`".to_owned(),
+            Some(Tok::Else) => "`else`".to_owned(),
+            Some(Tok::While) => "`while`".to_owned(),
+            Some(Tok::Return) => "`return`".to_owned(),
+            None => "end of file".to_owned(),
+        }
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Sym(s)) if *s == sym) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), CompileError> {
+        if !self.eat(sym) {
+            return self.error(format!("expected `{}`, found {}", sym, self.describe()));
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match self.peek() {
+            Some(Tok::Ident(name)) => {
+                self.pos += 1;
+                Ok(name.clone())
+            }
+            _ => self.error(format!("expected a name, found {}", self.describe())),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let line = self.line();
+        if self.peek() != Some(&Tok::Fn) {
+            return self.error(format!("expected `fn`, found {}", self.describe()));
+        }
+        self.pos += 1;
+        let name = self.ident()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if !self.eat(")") {
+            loop {
+                params.push(self.ident()?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        let body = self.block()?;
+        Ok(Function {
+            name,
+            params,
+            body,
+            line,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            if self.peek().is_none() {
+                return self.error("expected `}`, found end of file".to_owned());
+            }
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        match self.peek() {
+            Some(Tok::Let) => {
+                self.pos += 1;
+                let name = self.ident()?;
+                self.expect("=")?;
+                let value = self.expr(0)?;
+                self.expect(";")?;
+                Ok(Stmt::Let(name, value))
+            }
+            Some(Tok::If) => {
+                self.pos += 1;
+                let cond = self.expr(0)?;
+                let then = self.block()?;
+                let els = if self.peek() == Some(&Tok::Else) {
+                    self.pos += 1;
+                    if self.peek() == Some(&Tok::If) {
+                        vec![self.stmt()?]
+                    } else {
+                        self.block()?
+                    }
+                } else {
+                    Vec::new()
+                };
+                Ok(Stmt::If(cond, then, els))
+            }
+            Some(Tok::While) => {
+                self.pos += 1;
+                let cond = self.expr(0)?;
+                let body = self.block()?;
+                Ok(Stmt::While(cond, body))
+            }
+            Some(Tok::Return) => {
+                self.pos += 1;
+                if self.eat(";") {
+                    return Ok(Stmt::Return(None));
+                }
+                let value = self.expr(0)?;
+                self.expect(";")?;
+                Ok(Stmt::Return(Some(value)))
+            }
+            Some(Tok::Ident(name))
+                if self.tokens.get(self.pos + 1).map(|t| &t.tok) == Some(&Tok::Sym("=")) =>
+            {
+                let name = name.clone();
+                self.pos += 2;
+                let value = self.expr(0)?;
+                self.expect(";")?;
+                Ok(Stmt::Assign(name, value, line))
+            }
+            _ => {
+                let value = self.expr(0)?;
+                self.expect(";")?;
+                Ok(Stmt::Expr(value))
+            }
+        }
+    }
+
+    fn expr(&mut self, level: usize) -> Result<Expr, CompileError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.expr(level + 1)?;
+        'outer: loop {
+            for &(sym, op) in LEVELS[level] {
+                if self.eat(sym) {
+                    let rhs = self.expr(level + 1)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.eat("-") {
+            return Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?)));
+        }
+        if self.eat("!") {
+            return Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        match self.peek() {
+            Some(Tok::Num(n)) => {
+                self.pos += 1;
+                Ok(Expr::Num(*n))
+            }
+            Some(Tok::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                if !self.eat("(") {
+                    return Ok(Expr::Var(name, line));
+                }
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.expr(0)?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args, line))
+            }
+            Some(Tok::Sym("(")) => {
+                self.pos += 1;
+                let inner = self.expr(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            _ => self.error(format!("expected an expression, found {}", self.describe())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::lexer::lex;
+
+    fn parse_expr(source: &str) -> Expr {
+        let tokens = lex(source).unwrap();
+        Parser {
+            tokens: &tokens,
+            pos: 0,
+        }
+        .expr(0)
+        .unwrap()
+    }
+
+    #[test]
+    fn test_precedence() {
+        use Expr::*;
+        assert_eq!(
+            parse_expr("1 + 2 * 3 < 4 && !5"),
+            Binary(
+                BinOp::And,
+                Box::new(Binary(
+                    BinOp::Less,
+                    Box::new(Binary(
+                        BinOp::Add,
+                        Box::new(Num(1)),
+                        Box::new(Binary(BinOp::Mul, Box::new(Num(2)), Box::new(Num(3))))
+                    )),
+                    Box::new(Num(4))
+                )),
+                Box::new(Unary(UnOp::Not, Box::new(Num(5))))
+            )
+        );
+    }
+
+    #[test]
+    fn test_left_associative() {
+        use Expr::*;
+        assert_eq!(
+            parse_expr("1 - 2 - 3"),
+            Binary(
+                BinOp::Sub,
+                Box::new(Binary(BinOp::Sub, Box::new(Num(1)), Box::new(Num(2)))),
+                Box::new(Num(3))
+            )
+        );
+    }
+}
@@ -0,0 +1,1232 @@
+use crate::cache::{Decoded, InstructionCache};
+use crate::error::{Fault, VmError};
+use crate::instruction::{parse_instruction, DecodeError, OpCode, PMode};
+use crate::io::{Input, Output};
+use crate::limits::Limits;
+use crate::memory::Memory;
+use crate::profile::Profile;
+use crate::trace::{resolve_operands, MemWrite, TraceRecord, Tracer};
+use crate::watch::{operand_reads, MemAccess};
+use crate::Base;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// How many instructions run between checks of the wall clock.
+const CLOCK_CHECK_INTERVAL: u64 = 1024;
+
+/// An Intcode machine.
+///
+/// Input instructions first take values queued with `push_input` and then
+/// read from `input`. Every output value is written to `output`. `rb` is the
+/// relative base used by `PMode::Relative` parameters.
+///
+/// Execution can be capped with `set_limits`.
+///
+/// Decoded instructions are cached per address. Writes made by the program
+/// invalidate the entries they touch, and `mem_mut` drops the whole cache.
+///
+/// With `set_access_log` turned on, each step records the memory it read and
+/// wrote, available from `accesses` until the next step.
+///
+/// A tracer installed with `set_tracer` receives a `TraceRecord` for every
+/// executed instruction, and `set_profiling` keeps a `Profile` of where
+/// the program spends its time.
+pub struct Computer<I = VecDeque<Base>, O = Vec<Base>>
+where
+    I: Input,
+    O: Output,
+{
+    mem: Memory,
+    pub pc: usize,
+    pub rb: Base,
+    pub halted: bool,
+    pub name: String,
+    pub(crate) inbox: VecDeque<Base>,
+    pub(crate) input: I,
+    pub(crate) output: O,
+    cache: Option<InstructionCache>,
+    limits: Limits,
+    executed: u64,
+    started: Option<Instant>,
+    accesses: Option<Vec<MemAccess>>,
+    access_log: bool,
+    tracer: Option<Box<dyn Tracer + Send>>,
+    profile: Option<Profile>,
+}
+
+impl<I, O> Computer<I, O>
+where
+    I: Input + Clone,
+    O: Output + Clone,
+{
+    /// Creates an independent copy of the machine, including its input and
+    /// output. See `fork_with`.
+    pub fn fork(&self) -> Computer<I, O> {
+        self.fork_with(self.input.clone(), self.output.clone())
+    }
+}
+
+/// The result of successfully executing one instruction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StepOutcome {
+    Continue,
+    /// The instruction wrote this value to the output.
+    Output(Base),
+    /// The instruction is an input and no value was available. The program
+    /// counter still points at it.
+    NeedsInput,
+    Halted,
+}
+
+/// Why `Computer::run` returned control to the caller.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RunStatus {
+    NeedsInput,
+    ProducedOutput(Base),
+    Halted,
+}
+
+impl<I, O> Computer<I, O>
+where
+    I: Input,
+    O: Output,
+{
+    /// Creates a machine with its program counter at the start of `mem`.
+    pub fn new(name: &str, mem: Vec<Base>, input: I, output: O) -> Computer<I, O> {
+        Computer {
+            mem: Memory::new(mem),
+            pc: 0,
+            rb: 0,
+            halted: false,
+            name: name.to_owned(),
+            inbox: VecDeque::new(),
+            input,
+            output,
+            cache: Some(InstructionCache::default()),
+            limits: Limits::none(),
+            executed: 0,
+            started: None,
+            accesses: None,
+            access_log: false,
+            tracer: None,
+            profile: None,
+        }
+    }
+
+    pub fn mem(&self) -> &Memory {
+        &self.mem
+    }
+
+    /// Gives direct access to memory. Because the caller may change any cell,
+    /// this clears the decode cache.
+    pub fn mem_mut(&mut self) -> &mut Memory {
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+        &mut self.mem
+    }
+
+    /// Turns the decode cache on or off. It is on by default.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache = if enabled {
+            Some(InstructionCache::default())
+        } else {
+            None
+        };
+    }
+
+    pub fn input(&self) -> &I {
+        &self.input
+    }
+
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    pub fn output(&self) -> &O {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut O {
+        &mut self.output
+    }
+
+    /// Creates an independent copy of the machine that reads from `input` and
+    /// writes to `output`. Memory pages are shared with the original until
+    /// either side writes to them. The copy has no tracer, and starts with an
+    /// empty decode cache so that forking stays cheap.
+    pub fn fork_with<I2, O2>(&self, input: I2, output: O2) -> Computer<I2, O2>
+    where
+        I2: Input,
+        O2: Output,
+    {
+        Computer {
+            mem: self.mem.clone(),
+            pc: self.pc,
+            rb: self.rb,
+            halted: self.halted,
+            name: self.name.clone(),
+            inbox: self.inbox.clone(),
+            input,
+            output,
+            cache: self.cache.as_ref().map(|_| InstructionCache::default()),
+            limits: self.limits,
+            executed: self.executed,
+            started: None,
+            accesses: if self.access_log {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            access_log: self.access_log,
+            tracer: None,
+            profile: self.profile.as_ref().map(|_| Profile::default()),
+        }
+    }
+
+    /// Sets execution limits. The wall clock for `max_duration` restarts at
+    /// the next step.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.started = None;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// The number of instructions executed so far.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Turns recording of memory accesses on or off. It is off by default,
+    /// and always on while a tracer is installed.
+    pub fn set_access_log(&mut self, enabled: bool) {
+        self.access_log = enabled;
+        self.update_access_log();
+    }
+
+    fn update_access_log(&mut self) {
+        let enabled = self.access_log || self.tracer.is_some();
+        if enabled != self.accesses.is_some() {
+            self.accesses = if enabled { Some(Vec::new()) } else { None };
+        }
+    }
+
+    /// Installs a tracer, replacing any previous one.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer + Send>) {
+        self.tracer = Some(tracer);
+        self.update_access_log();
+    }
+
+    /// Removes the tracer so the caller can `finish` it.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer + Send>> {
+        let tracer = self.tracer.take();
+        self.update_access_log();
+        tracer
+    }
+
+    /// The memory accesses made by the most recent step, in the order they
+    /// happened. Empty unless the access log is on.
+    pub fn accesses(&self) -> &[MemAccess] {
+        self.accesses.as_deref().unwrap_or(&[])
+    }
+
+    /// Turns profiling on with empty counts, or off. It is off by default.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = if enabled {
+            Some(Profile::default())
+        } else {
+            None
+        };
+    }
+
+    /// The counts collected since profiling was turned on.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Queues a value for the next input instruction.
+    pub fn push_input(&mut self, value: Base) {
+        self.inbox.push_back(value);
+    }
+
+    /// Runs the program until it halts. Memory is updated in place and the
+    /// loop uses constant stack, so long-running programs are fine.
+    ///
+    /// Running out of input is treated as the input being closed.
+    pub fn compute(&mut self) -> Result<(), VmError> {
+        loop {
+            match self.step()? {
+                StepOutcome::Continue | StepOutcome::Output(_) => {}
+                StepOutcome::NeedsInput => {
+                    let instruction = self.mem.get(self.pc).unwrap_or(0);
+                    return Err(Fault::InputClosed.at(self.pc, instruction));
+                }
+                StepOutcome::Halted => return Ok(()),
+            }
+        }
+    }
+
+    /// Runs the program until it halts, produces an output or waits for
+    /// input. After `NeedsInput` the caller can `push_input` and call `run`
+    /// again to resume where the machine left off.
+    pub fn run(&mut self) -> Result<RunStatus, VmError> {
+        loop {
+            match self.step()? {
+                StepOutcome::Continue => {}
+                StepOutcome::Output(value) => return Ok(RunStatus::ProducedOutput(value)),
+                StepOutcome::NeedsInput => return Ok(RunStatus::NeedsInput),
+                StepOutcome::Halted => return Ok(RunStatus::Halted),
+            }
+        }
+    }
+
+    /// Executes the instruction at the program counter. Stepping a halted
+    /// machine does nothing.
+    ///
+    /// On error the machine is left as it was before the faulting instruction.
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        if self.halted {
+            return Ok(StepOutcome::Halted);
+        }
+        if let Some(log) = &mut self.accesses {
+            log.clear();
+        }
+
+        let pc = self.pc;
+        let rb = self.rb;
+        let decoded = self.decode(pc)?;
+        let operands = match self.tracer {
+            Some(_) => resolve_operands(&self.mem, rb, &decoded.op, pc),
+            None => Vec::new(),
+        };
+        let outcome = self
+            .check_limits()
+            .and_then(|()| self.execute(decoded.op, pc))
+            .map_err(|fault| fault.at(pc, decoded.word))?;
+        if outcome != StepOutcome::NeedsInput {
+            self.executed += 1;
+            if let Some(profile) = &mut self.profile {
+                profile.record(pc, &decoded.op, self.pc);
+            }
+            if let Some(tracer) = &mut self.tracer {
+                let writes: Vec<MemWrite> = self
+                    .accesses
+                    .iter()
+                    .flatten()
+                    .filter_map(|access| match *access {
+                        MemAccess::Write { addr, old, new } => Some(MemWrite { addr, old, new }),
+                        MemAccess::Read { .. } => None,
+                    })
+                    .collect();
+                let input = match decoded.op {
+                    OpCode::Input { .. } => writes.first().map(|w| w.new),
+                    _ => None,
+                };
+                let output = match outcome {
+                    StepOutcome::Output(value) => Some(value),
+                    _ => None,
+                };
+                tracer.record(&TraceRecord {
+                    step: self.executed - 1,
+                    pc,
+                    rb,
+                    word: decoded.word,
+                    op: decoded.op,
+                    operands,
+                    writes,
+                    input,
+                    output,
+                });
+            }
+        }
+        Ok(outcome)
+    }
+
+    fn check_limits(&mut self) -> Result<(), Fault> {
+        if let Some(max) = self.limits.max_instructions {
+            if self.executed >= max {
+                return Err(Fault::InstructionLimit(max));
+            }
+        }
+        if let Some(max) = self.limits.max_duration {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            if self.executed.is_multiple_of(CLOCK_CHECK_INTERVAL) && started.elapsed() >= max {
+                return Err(Fault::TimeLimit(max));
+            }
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        match (self.started, self.limits.max_duration) {
+            (Some(started), Some(max)) => Some(started + max),
+            _ => None,
+        }
+    }
+
+    fn decode(&mut self, pc: usize) -> Result<Decoded, VmError> {
+        if let Some(decoded) = self.cache.as_ref().and_then(|cache| cache.get(pc)) {
+            return Ok(decoded);
+        }
+
+        let word = self.mem.get(pc).map_err(|err| Fault::from(err).at(pc, 0))?;
+        let op = parse_instruction(word).map_err(|err| match err {
+            DecodeError::UnknownOpCode => VmError::UnknownOpCode {
+                pc,
+                instruction: word,
+            },
+            DecodeError::UnknownPMode => VmError::UnknownPMode {
+                pc,
+                instruction: word,
+            },
+        })?;
+        let decoded = Decoded { word, op };
+        if let Some(cache) = &mut self.cache {
+            cache.insert(pc, decoded);
+        }
+        Ok(decoded)
+    }
+
+    /// Writes a cell on behalf of the program. Every write an instruction
+    /// makes goes through here.
+    fn store(&mut self, addr: usize, value: Base) -> Result<(), Fault> {
+        let old = match self.accesses {
+            Some(_) => self.mem.get(addr)?,
+            None => 0,
+        };
+        self.mem.set(addr, value)?;
+        self.invalidate(addr);
+        if let Some(log) = &mut self.accesses {
+            log.push(MemAccess::Write {
+                addr,
+                old,
+                new: value,
+            });
+        }
+        Ok(())
+    }
+
+    fn log_reads(&mut self, op: &OpCode, pc: usize) {
+        if let Some(log) = &mut self.accesses {
+            for addr in operand_reads(&self.mem, self.rb, op, pc) {
+                let value = self.mem.get(addr).unwrap_or(0);
+                log.push(MemAccess::Read { addr, value });
+            }
+        }
+    }
+
+    fn invalidate(&mut self, addr: usize) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(addr);
+        }
+    }
+
+    fn execute(&mut self, cmd: OpCode, pc: usize) -> Result<StepOutcome, Fault> {
+        self.log_reads(&cmd, pc);
+        match cmd {
+            OpCode::Add { p_modes } => {
+                let (out, value) = op_add(&self.mem, self.rb, p_modes, pc)?;
+                self.store(out, value)?;
+                self.pc += 4;
+            }
+            OpCode::Multiply { p_modes } => {
+                let (out, value) = op_mult(&self.mem, self.rb, p_modes, pc)?;
+                self.store(out, value)?;
+                self.pc += 4;
+            }
+            OpCode::Input { p_modes } => {
+                if !self.op_input(p_modes, pc)? {
+                    return Ok(StepOutcome::NeedsInput);
+                }
+                self.pc += 2;
+            }
+            OpCode::Output { p_modes } => {
+                let value = self.op_output(p_modes, pc)?;
+                self.pc += 2;
+                return Ok(StepOutcome::Output(value));
+            }
+            OpCode::JmpT { p_modes } => {
+                self.op_jump_t(p_modes)?;
+            }
+            OpCode::JmpF { p_modes } => {
+                self.op_jump_f(p_modes)?;
+            }
+            OpCode::Less { p_modes } => {
+                self.op_less(p_modes)?;
+            }
+            OpCode::Eq { p_modes } => {
+                self.op_eq(p_modes)?;
+            }
+            OpCode::AdjustBase { p_modes } => {
+                self.op_adjust_base(p_modes, pc)?;
+                self.pc += 2;
+            }
+            OpCode::Halt => {
+                self.halted = true;
+                return Ok(StepOutcome::Halted);
+            }
+        }
+        Ok(StepOutcome::Continue)
+    }
+
+    /// Returns `false` without consuming anything when no input is available.
+    fn op_input(&mut self, p_modes: [PMode; 1], pc: usize) -> Result<bool, Fault> {
+        let out = addr(&self.mem, self.rb, p_modes[0], pc + 1)?;
+        // Check the destination before taking a value so a bad write can't
+        // lose it.
+        self.mem.get(out)?;
+        let deadline = self.deadline();
+        let val = match self.inbox.pop_front() {
+            Some(v) => Some(v),
+            None => match deadline {
+                Some(deadline) => self.input.read_before(deadline),
+                None => self.input.read(),
+            },
+        };
+        let val = match val {
+            Some(v) => v,
+            None => match (deadline, self.limits.max_duration) {
+                (Some(deadline), Some(max)) if Instant::now() >= deadline => {
+                    return Err(Fault::TimeLimit(max));
+                }
+                _ => return Ok(false),
+            },
+        };
+        self.store(out, val)?;
+        Ok(true)
+    }
+
+    fn op_output(&mut self, p_modes: [PMode; 1], pc: usize) -> Result<Base, Fault> {
+        let a = get(&self.mem, self.rb, p_modes[0], pc + 1)?;
+        self.output.write(a).map_err(|_| Fault::OutputClosed)?;
+        Ok(a)
+    }
+
+    fn op_adjust_base(&mut self, p_modes: [PMode; 1], pc: usize) -> Result<(), Fault> {
+        let offset = get(&self.mem, self.rb, p_modes[0], pc + 1)?;
+        self.rb = relative(&self.mem, self.rb, offset)?;
+        Ok(())
+    }
+
+    fn op_jump_t(&mut self, p_modes: [PMode; 2]) -> Result<(), Fault> {
+        let cmp = get(&self.mem, self.rb, p_modes[0], self.pc + 1)?;
+        let nxt = to_addr(get(&self.mem, self.rb, p_modes[1], self.pc + 2)?)?;
+        if cmp != 0 {
+            self.pc = nxt;
+        } else {
+            self.pc += 3;
+        }
+        Ok(())
+    }
+
+    fn op_jump_f(&mut self, p_modes: [PMode; 2]) -> Result<(), Fault> {
+        let cmp = get(&self.mem, self.rb, p_modes[0], self.pc + 1)?;
+        let nxt = to_addr(get(&self.mem, self.rb, p_modes[1], self.pc + 2)?)?;
+        if cmp == 0 {
+            self.pc = nxt;
+        } else {
+            self.pc += 3;
+        }
+        Ok(())
+    }
+
+    fn op_less(&mut self, p_modes: [PMode; 3]) -> Result<(), Fault> {
+        let a = get(&self.mem, self.rb, p_modes[0], self.pc + 1)?;
+        let b = get(&self.mem, self.rb, p_modes[1], self.pc + 2)?;
+        let out = addr(&self.mem, self.rb, p_modes[2], self.pc + 3)?;
+
+        if a < b {
+            self.store(out, 1)?;
+        } else {
+            self.store(out, 0)?;
+        }
+        self.pc += 4;
+        Ok(())
+    }
+
+    fn op_eq(&mut self, p_modes: [PMode; 3]) -> Result<(), Fault> {
+        let a = get(&self.mem, self.rb, p_modes[0], self.pc + 1)?;
+        let b = get(&self.mem, self.rb, p_modes[1], self.pc + 2)?;
+        let out = addr(&self.mem, self.rb, p_modes[2], self.pc + 3)?;
+
+        if a == b {
+            self.store(out, 1)?;
+        } else {
+            self.store(out, 0)?;
+        }
+        self.pc += 4;
+        Ok(())
+    }
+}
+
+fn get(input: &Memory, rb: Base, p_mode: PMode, idx: usize) -> Result<Base, Fault> {
+    match p_mode {
+        PMode::Immediate => Ok(input.get(idx)?),
+        _ => Ok(input.get(addr(input, rb, p_mode, idx)?)?),
+    }
+}
+
+/// Resolves the address a parameter refers to.
+fn addr(input: &Memory, rb: Base, p_mode: PMode, idx: usize) -> Result<usize, Fault> {
+    match p_mode {
+        PMode::Position => to_addr(input.get(idx)?),
+        PMode::Relative => to_addr(relative(input, rb, input.get(idx)?)?),
+        PMode::Immediate => Err(Fault::ImmediateWrite),
+    }
+}
+
+/// Adds `offset` to the relative base. A sum too large to represent lies
+/// beyond the memory limit, and one too small is a negative address.
+fn relative(input: &Memory, rb: Base, offset: Base) -> Result<Base, Fault> {
+    rb.checked_add(offset).ok_or(if offset < 0 {
+        Fault::NegativeAddress(Base::MIN)
+    } else {
+        Fault::AddressOutOfRange(usize::MAX, input.limit())
+    })
+}
+
+fn to_addr(value: Base) -> Result<usize, Fault> {
+    if value < 0 {
+        return Err(Fault::NegativeAddress(value));
+    }
+    Ok(value as usize)
+}
+
+/// Returns the address to write and the value to write there. Sums wrap
+/// around on overflow.
+fn op_add(
+    input: &Memory,
+    rb: Base,
+    p_modes: [PMode; 3],
+    pc: usize,
+) -> Result<(usize, Base), Fault> {
+    let a = get(input, rb, p_modes[0], pc + 1)?;
+    let b = get(input, rb, p_modes[1], pc + 2)?;
+    let out = addr(input, rb, p_modes[2], pc + 3)?;
+    Ok((out, a.wrapping_add(b)))
+}
+
+/// Returns the address to write and the value to write there. Products
+/// wrap around on overflow.
+fn op_mult(
+    input: &Memory,
+    rb: Base,
+    p_modes: [PMode; 3],
+    pc: usize,
+) -> Result<(usize, Base), Fault> {
+    let a = get(input, rb, p_modes[0], pc + 1)?;
+    let b = get(input, rb, p_modes[1], pc + 2)?;
+    let out = addr(input, rb, p_modes[2], pc + 3)?;
+    Ok((out, a.wrapping_mul(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::IterInput;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_add() {
+        let mut input = Memory::from(vec![1, 5, 6, 0, 99, 2, 3]);
+        let (out, value) = op_add(
+            &input,
+            0,
+            [PMode::Position, PMode::Position, PMode::Position],
+            0,
+        )
+        .unwrap();
+        input.set(out, value).unwrap();
+
+        assert_eq!(input.to_vec(), &[5, 5, 6, 0, 99, 2, 3]);
+    }
+
+    #[test]
+    fn test_add_neg() {
+        let mut input = Memory::from(vec![1, 5, 6, 0, 99, 2, -3]);
+        let (out, value) = op_add(
+            &input,
+            0,
+            [PMode::Position, PMode::Position, PMode::Position],
+            0,
+        )
+        .unwrap();
+        input.set(out, value).unwrap();
+
+        assert_eq!(input.to_vec(), &[-1, 5, 6, 0, 99, 2, -3]);
+    }
+
+    #[test]
+    fn test_add_imm() {
+        let mut input = Memory::from(vec![1, 5, 6, 3, 99, 2, 3]);
+        let (out, value) = op_add(
+            &input,
+            0,
+            [PMode::Immediate, PMode::Position, PMode::Position],
+            0,
+        )
+        .unwrap();
+        input.set(out, value).unwrap();
+
+        assert_eq!(input.to_vec(), &[1, 5, 6, 8, 99, 2, 3]);
+    }
+
+    #[test]
+    fn test_add_relative() {
+        let mut input = Memory::from(vec![22201, 0, 1, -4, 99, 2, 3]);
+        let (out, value) = op_add(
+            &input,
+            5,
+            [PMode::Relative, PMode::Relative, PMode::Relative],
+            0,
+        )
+        .unwrap();
+        input.set(out, value).unwrap();
+
+        assert_eq!(input.to_vec(), &[22201, 5, 1, -4, 99, 2, 3]);
+    }
+
+    #[test]
+    fn test_mult() {
+        let mut input = Memory::from(vec![1, 5, 6, 0, 99, 2, 3]);
+        let (out, value) = op_mult(
+            &input,
+            0,
+            [PMode::Position, PMode::Position, PMode::Position],
+            0,
+        )
+        .unwrap();
+        input.set(out, value).unwrap();
+
+        assert_eq!(input.to_vec(), &[6, 5, 6, 0, 99, 2, 3]);
+    }
+
+    #[test]
+    fn test_arithmetic_wraps() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![
+                1101,
+                Base::MAX,
+                1,
+                13,
+                1102,
+                Base::MAX,
+                2,
+                14,
+                99,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+        assert_eq!(cmp.mem().get(13), Ok(Base::MIN));
+        assert_eq!(cmp.mem().get(14), Ok(-2));
+    }
+
+    #[test]
+    fn test_op_input() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![3, 5, 6, 0, 99, 2, 3],
+            IterInput(Some(42).into_iter()),
+            Vec::new(),
+        );
+        cmp.op_input([PMode::Position], 0).unwrap();
+
+        assert_eq!(cmp.mem().to_vec(), &[3, 5, 6, 0, 99, 42, 3]);
+    }
+
+    #[test]
+    fn test_op_input_channel() {
+        let (tx, rx) = channel();
+        tx.send(42).unwrap();
+        let mut cmp = Computer::new("TestUnit", vec![3, 5, 6, 0, 99, 2, 3], &rx, &tx);
+        cmp.op_input([PMode::Position], 0).unwrap();
+
+        assert_eq!(cmp.mem().to_vec(), &[3, 5, 6, 0, 99, 42, 3]);
+    }
+
+    #[test]
+    fn test_op_output() {
+        let mut out = Vec::new();
+        let mut cmp = Computer::new("TestUnit", vec![104, 7, 99], VecDeque::new(), |v| {
+            out.push(v);
+            Ok(())
+        });
+        cmp.compute().unwrap();
+        drop(cmp);
+
+        assert_eq!(out, &[7]);
+    }
+
+    #[test]
+    fn test_adjust_base() {
+        let mut out = Vec::new();
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![109, 5, 204, 1, 99, 0, 42],
+            VecDeque::new(),
+            |v| {
+                out.push(v);
+                Ok(())
+            },
+        );
+        cmp.compute().unwrap();
+        assert_eq!(cmp.rb, 5);
+        drop(cmp);
+
+        assert_eq!(out, &[42]);
+    }
+
+    #[test]
+    fn test_input_relative() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![109, 3, 203, 3, 99, 0, 0],
+            IterInput(Some(42).into_iter()),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+
+        assert_eq!(cmp.mem().to_vec(), &[109, 3, 203, 3, 99, 0, 42]);
+    }
+
+    #[test]
+    fn test_grows_memory() {
+        let mut out = Vec::new();
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ],
+            VecDeque::new(),
+            |v| {
+                out.push(v);
+                Ok(())
+            },
+        );
+        cmp.compute().unwrap();
+        drop(cmp);
+
+        assert_eq!(
+            out,
+            &[109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99]
+        );
+    }
+
+    #[test]
+    fn test_jump_to_high_address() {
+        // Writes HLT far past the program and jumps to it.
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1101, 99, 0, 16_000_000, 1105, 1, 16_000_000],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+        assert_eq!(cmp.pc, 16_000_000);
+        assert!(cmp.cache.as_ref().unwrap().get(16_000_000).is_some());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1101, 1, 1, 64, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        cmp.mem_mut().set_limit(32);
+        let expected = VmError::AddressOutOfRange {
+            pc: 0,
+            instruction: 1101,
+            addr: 64,
+            limit: 32,
+        };
+        assert_eq!(cmp.compute(), Err(expected));
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1, 0, 0, 0, 42],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        let expected = VmError::UnknownOpCode {
+            pc: 4,
+            instruction: 42,
+        };
+        assert_eq!(cmp.compute(), Err(expected));
+        assert_eq!(cmp.pc, 4);
+    }
+
+    #[test]
+    fn test_unknown_pmode() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![301, 0, 0, 0, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        let expected = VmError::UnknownPMode {
+            pc: 0,
+            instruction: 301,
+        };
+        assert_eq!(cmp.step(), Err(expected));
+    }
+
+    #[test]
+    fn test_negative_address() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1, -1, 0, 0, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        let expected = VmError::NegativeAddress {
+            pc: 0,
+            instruction: 1,
+            addr: -1,
+        };
+        assert_eq!(cmp.step(), Err(expected));
+    }
+
+    #[test]
+    fn test_relative_overflow() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![109, Base::MAX, 209, 5, 99, 0],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        assert_eq!(cmp.step(), Ok(StepOutcome::Continue));
+        let expected = VmError::AddressOutOfRange {
+            pc: 2,
+            instruction: 209,
+            addr: usize::MAX,
+            limit: cmp.mem().limit(),
+        };
+        assert_eq!(cmp.step(), Err(expected));
+
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![109, Base::MIN, 109, -1, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        assert_eq!(cmp.step(), Ok(StepOutcome::Continue));
+        let expected = VmError::NegativeAddress {
+            pc: 2,
+            instruction: 109,
+            addr: Base::MIN,
+        };
+        assert_eq!(cmp.step(), Err(expected));
+    }
+
+    #[test]
+    fn test_immediate_write() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![11101, 1, 1, 0, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        let expected = VmError::ImmediateWrite {
+            pc: 0,
+            instruction: 11101,
+        };
+        assert_eq!(cmp.step(), Err(expected));
+        assert_eq!(cmp.mem().to_vec(), &[11101, 1, 1, 0, 99]);
+    }
+
+    #[test]
+    fn test_input_closed() {
+        let mut cmp = Computer::new("TestUnit", vec![3, 0, 99], VecDeque::new(), Vec::new());
+        let expected = VmError::InputClosed {
+            pc: 0,
+            instruction: 3,
+        };
+        assert_eq!(cmp.step(), Ok(StepOutcome::NeedsInput));
+        assert_eq!(cmp.compute(), Err(expected));
+    }
+
+    #[test]
+    fn test_output_closed() {
+        let (tx, rx) = channel();
+        drop(rx);
+        let mut cmp = Computer::new("TestUnit", vec![104, 1, 99], VecDeque::new(), &tx);
+        let expected = VmError::OutputClosed {
+            pc: 0,
+            instruction: 104,
+        };
+        assert_eq!(cmp.step(), Err(expected));
+    }
+
+    #[test]
+    fn test_step_halted() {
+        let mut cmp = Computer::new("TestUnit", vec![99], VecDeque::new(), Vec::new());
+        assert_eq!(cmp.step(), Ok(StepOutcome::Halted));
+        assert_eq!(cmp.step(), Ok(StepOutcome::Halted));
+    }
+
+    #[test]
+    fn test_run_resumes_after_input() {
+        // Reads two values and outputs their sum.
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99, 0, 0, 0],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        assert_eq!(cmp.run(), Ok(RunStatus::NeedsInput));
+        assert_eq!(cmp.pc, 0);
+        cmp.push_input(3);
+        assert_eq!(cmp.run(), Ok(RunStatus::NeedsInput));
+        assert_eq!(cmp.pc, 2);
+        cmp.push_input(4);
+        assert_eq!(cmp.run(), Ok(RunStatus::ProducedOutput(7)));
+        assert_eq!(cmp.run(), Ok(RunStatus::Halted));
+        assert_eq!(cmp.run(), Ok(RunStatus::Halted));
+    }
+
+    #[test]
+    fn test_fork() {
+        // Reads a value and outputs it doubled.
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        assert_eq!(cmp.run(), Ok(RunStatus::NeedsInput));
+
+        let mut forks: Vec<_> = (0..3).map(|_| cmp.fork()).collect();
+        assert!(cmp.cache.as_ref().unwrap().get(0).is_some());
+        for (i, fork) in forks.iter_mut().enumerate() {
+            assert_eq!(fork.mem().shared_pages(cmp.mem()), 1);
+            assert!(fork.cache.as_ref().unwrap().get(0).is_none());
+            fork.push_input(i as Base);
+            fork.compute().unwrap();
+        }
+
+        let outputs: Vec<_> = forks.iter().map(|f| f.output().clone()).collect();
+        assert_eq!(outputs, vec![vec![0], vec![2], vec![4]]);
+        assert_eq!(forks[0].mem().shared_pages(cmp.mem()), 0);
+        assert_eq!(cmp.pc, 0);
+        assert_eq!(cmp.mem().get(9), Ok(0));
+    }
+
+    #[test]
+    fn test_run_feedback_loop_single_thread() {
+        let program = vec![
+            3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54,
+            -5, 54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4,
+            53, 1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+        ];
+        let mut amps: Vec<_> = [9, 7, 8, 5, 6]
+            .iter()
+            .map(|&phase| {
+                let mut cmp =
+                    Computer::new("TestUnit", program.clone(), VecDeque::new(), Vec::new());
+                cmp.push_input(phase);
+                cmp
+            })
+            .collect();
+
+        let mut signal = 0;
+        let mut last_output = None;
+        'outer: loop {
+            for (i, amp) in amps.iter_mut().enumerate() {
+                amp.push_input(signal);
+                match amp.run().unwrap() {
+                    RunStatus::ProducedOutput(value) => signal = value,
+                    RunStatus::Halted => break 'outer,
+                    RunStatus::NeedsInput => panic!("amplifier {} starved", i),
+                }
+                if i == 4 {
+                    last_output = Some(signal);
+                }
+            }
+        }
+
+        assert_eq!(last_output, Some(18216));
+    }
+
+    #[test]
+    fn test_compute_long_running() {
+        // Counts a cell down from one million, executing two million
+        // instructions before halting.
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1001, 8, -1, 8, 1005, 8, 0, 99, 1_000_000],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+        assert!(cmp.halted);
+        assert_eq!(cmp.mem().get(8), Ok(0));
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // Outputs 7, then rewrites that output's operand and turns its own
+        // first instruction into a halt before jumping back to the start.
+        let program = vec![104, 7, 1101, 0, 8, 1, 1101, 0, 99, 2, 1105, 1, 0];
+        let mut cached = Computer::new("TestUnit", program.clone(), VecDeque::new(), Vec::new());
+        let mut uncached = Computer::new("TestUnit", program, VecDeque::new(), Vec::new());
+        uncached.set_decode_cache(false);
+
+        cached.compute().unwrap();
+        uncached.compute().unwrap();
+
+        assert_eq!(cached.output(), uncached.output());
+        assert_eq!(cached.output(), &[7, 8]);
+    }
+
+    #[test]
+    fn test_access_log() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1001, 7, 5, 7, 3, 7, 99, 10],
+            IterInput(Some(3).into_iter()),
+            Vec::new(),
+        );
+        cmp.step().unwrap();
+        assert!(cmp.accesses().is_empty());
+
+        cmp.set_access_log(true);
+        cmp.step().unwrap();
+        assert_eq!(
+            cmp.accesses(),
+            &[MemAccess::Write {
+                addr: 7,
+                old: 15,
+                new: 3
+            }]
+        );
+
+        cmp.pc = 0;
+        cmp.step().unwrap();
+        assert_eq!(
+            cmp.accesses(),
+            &[
+                MemAccess::Read { addr: 7, value: 3 },
+                MemAccess::Write {
+                    addr: 7,
+                    old: 3,
+                    new: 8
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mem_mut_clears_cache() {
+        let mut cmp = Computer::new("TestUnit", vec![99, 99], VecDeque::new(), Vec::new());
+        cmp.decode(0).unwrap();
+        cmp.mem_mut().set(0, 104).unwrap();
+        assert_eq!(cmp.run(), Ok(RunStatus::ProducedOutput(99)));
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        let mut cmp = Computer::new("TestUnit", vec![1105, 1, 0], VecDeque::new(), Vec::new());
+        cmp.set_limits(Limits::instructions(1000));
+        let expected = VmError::InstructionLimit {
+            pc: 0,
+            instruction: 1105,
+            limit: 1000,
+        };
+        assert_eq!(cmp.compute(), Err(expected));
+        assert_eq!(cmp.executed(), 1000);
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let mut cmp = Computer::new("TestUnit", vec![1105, 1, 0], VecDeque::new(), Vec::new());
+        cmp.set_limits(Limits::duration(Duration::from_millis(20)));
+        match cmp.compute() {
+            Err(VmError::TimeLimit { pc: 0, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_time_limit_while_blocked() {
+        let (tx, rx) = channel::<Base>();
+        let mut cmp = Computer::new("TestUnit", vec![3, 0, 99], &rx, Vec::new());
+        cmp.set_limits(Limits::duration(Duration::from_millis(20)));
+        match cmp.compute() {
+            Err(VmError::TimeLimit { pc: 0, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        drop(tx);
+    }
+
+    #[test]
+    fn test_interpret_case1() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1, 0, 0, 0, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+        assert!(cmp.halted);
+        assert_eq!(cmp.mem().to_vec(), &[2, 0, 0, 0, 99]);
+    }
+
+    #[test]
+    fn test_interpret_case2() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![2, 3, 0, 3, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+        assert!(cmp.halted);
+        assert_eq!(cmp.mem().to_vec(), &[2, 3, 0, 6, 99]);
+    }
+
+    #[test]
+    fn test_interpret_case3() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![2, 4, 4, 5, 99, 0],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        cmp.compute().unwrap();
+        assert!(cmp.halted);
+        assert_eq!(cmp.mem().to_vec(), &[2, 4, 4, 5, 99, 9801]);
+    }
+
+    #[test]
+    fn test_interpret_case4() {
+        let mut cmp = Computer::new(
+            "TestUnit",
+            vec![1, 1, 1, 4, 99, 5, 6, 0, 99],
+            VecDeque::new(),
+            Vec::new(),
+        );
+        while !cmp.halted {
+            cmp.step().unwrap();
+        }
+        assert!(cmp.halted);
+        assert_eq!(cmp.mem().to_vec(), &[30, 1, 1, 4, 2, 5, 6, 0, 99]);
+    }
+}
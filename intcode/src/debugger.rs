@@ -0,0 +1,481 @@
+use crate::computer::{Computer, StepOutcome};
+use crate::disasm::{disassemble_at, line_at};
+use crate::watch::{MemAccess, Watchpoint};
+use crate::Base;
+use std::collections::BTreeSet;
+
+const HELP: &str = "\
+step [n]         execute n instructions (default 1)
+continue         run until a breakpoint, halt, error or input is needed
+break <addr>     stop before executing the instruction at addr
+delete <addr>    remove a breakpoint
+breakpoints      list breakpoints
+watch <a> [b]    stop after cells a..=b are written
+rwatch <a> [b]   stop after cells a..=b are read
+awatch <a> [b]   stop after cells a..=b are read or written
+unwatch <a> [b]  remove the watchpoint on a..=b
+watchpoints      list watchpoints
+regs             show pc and rb
+x <addr> [n]     show n memory cells starting at addr (default 8)
+set <addr> <v>   write v to memory at addr
+disas [addr] [n] disassemble n instructions from addr (default pc, 1)
+input <v>...     queue values for input instructions
+quit             leave the debugger";
+
+/// What the front end should do after a command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    Text(String),
+    Quit,
+}
+
+/// A command-driven debugger around a `Computer`.
+///
+/// `execute` takes one line of input, as typed at a prompt, and returns the
+/// text to show. The `intdbg` binary wraps it in a REPL.
+pub struct Debugger {
+    cmp: Computer,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Debugger {
+    pub fn new(cmp: Computer) -> Debugger {
+        Debugger {
+            cmp,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    pub fn computer(&self) -> &Computer {
+        &self.cmp
+    }
+
+    pub fn computer_mut(&mut self) -> &mut Computer {
+        &mut self.cmp
+    }
+
+    /// Runs one command line.
+    pub fn execute(&mut self, line: &str) -> Reply {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (cmd, args) = match words.split_first() {
+            Some((cmd, args)) => (*cmd, args),
+            None => return Reply::Text(String::new()),
+        };
+        let text = match cmd {
+            "s" | "step" => parse_args(args, 0, 1).map(|n| self.step(n.first().copied())),
+            "c" | "continue" => parse_args(args, 0, 0).map(|_| self.cont()),
+            "b" | "break" => parse_args(args, 1, 1).map(|a| {
+                self.breakpoints.insert(a[0]);
+                format!("breakpoint at {}", a[0])
+            }),
+            "delete" => parse_args(args, 1, 1).map(|a| {
+                if self.breakpoints.remove(&a[0]) {
+                    format!("deleted breakpoint at {}", a[0])
+                } else {
+                    format!("no breakpoint at {}", a[0])
+                }
+            }),
+            "breakpoints" => parse_args(args, 0, 0).map(|_| self.list_breakpoints()),
+            "watch" => parse_args(args, 1, 2).and_then(|a| self.watch(&a, false, true)),
+            "rwatch" => parse_args(args, 1, 2).and_then(|a| self.watch(&a, true, false)),
+            "awatch" => parse_args(args, 1, 2).and_then(|a| self.watch(&a, true, true)),
+            "unwatch" => parse_args(args, 1, 2).and_then(|a| self.unwatch(&a)),
+            "watchpoints" => parse_args(args, 0, 0).map(|_| self.list_watchpoints()),
+            "r" | "regs" => parse_args(args, 0, 0).map(|_| self.regs()),
+            "x" => parse_args(args, 1, 2).and_then(|a| self.examine(a[0], a.get(1).copied())),
+            "set" => self.set(args),
+            "d" | "disas" => parse_args(args, 0, 2).map(|a| {
+                let addr = a.first().copied().unwrap_or(self.cmp.pc);
+                self.disassemble(addr, a.get(1).copied().unwrap_or(1))
+            }),
+            "i" | "input" => self.input(args),
+            "h" | "help" => Ok(HELP.to_owned()),
+            "q" | "quit" => return Reply::Quit,
+            _ => Err(format!("unknown command `{}`; try `help`", cmd)),
+        };
+        Reply::Text(text.unwrap_or_else(|err| err))
+    }
+
+    /// Describes the instruction at the program counter.
+    pub fn location(&self) -> String {
+        if self.cmp.halted {
+            return "halted".to_owned();
+        }
+        self.disassemble(self.cmp.pc, 1)
+    }
+
+    fn step(&mut self, count: Option<usize>) -> String {
+        let mut lines = Vec::new();
+        for _ in 0..count.unwrap_or(1) {
+            if let Some(stop) = self.step_once(&mut lines) {
+                lines.push(stop);
+                return lines.join("\n");
+            }
+        }
+        lines.push(self.location());
+        lines.join("\n")
+    }
+
+    fn cont(&mut self) -> String {
+        let mut lines = Vec::new();
+        loop {
+            if let Some(stop) = self.step_once(&mut lines) {
+                lines.push(stop);
+                break;
+            }
+            if self.breakpoints.contains(&self.cmp.pc) {
+                lines.push(format!("breakpoint at {}", self.cmp.pc));
+                lines.push(self.location());
+                break;
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Executes one instruction, noting any output in `lines`. Returns a
+    /// message if execution can't continue or a watchpoint was hit.
+    fn step_once(&mut self, lines: &mut Vec<String>) -> Option<String> {
+        // The instruction may overwrite itself, so describe it up front.
+        let pc = self.cmp.pc;
+        let instruction = if self.watchpoints.is_empty() {
+            None
+        } else {
+            disassemble_at(self.cmp.mem(), pc).map(|(text, _)| text)
+        };
+
+        let outcome = self.cmp.step();
+        if let Ok(StepOutcome::Output(value)) = outcome {
+            lines.push(format!("output: {}", value));
+        }
+        let stop = match outcome {
+            Ok(StepOutcome::Continue) | Ok(StepOutcome::Output(_)) => None,
+            Ok(StepOutcome::NeedsInput) => Some(format!(
+                "waiting for input at {}; queue values with `input`",
+                self.cmp.pc
+            )),
+            Ok(StepOutcome::Halted) => Some("halted".to_owned()),
+            Err(err) => Some(format!("error: {}", err)),
+        };
+
+        // A step that hit a watchpoint may also have faulted or halted, so
+        // report both, or stepping again would only hit the watchpoint again.
+        if let Some(instruction) = instruction {
+            let hits = self.watch_hits(pc, &instruction);
+            if !hits.is_empty() {
+                let stop = stop.unwrap_or_else(|| self.location());
+                return Some(format!("{}\n{}", hits.join("\n"), stop));
+            }
+        }
+        stop
+    }
+
+    fn watch_hits(&self, pc: usize, instruction: &str) -> Vec<String> {
+        self.cmp
+            .accesses()
+            .iter()
+            .filter(|access| self.watchpoints.iter().any(|wp| wp.matches(access)))
+            .map(|access| match *access {
+                MemAccess::Read { addr, value } => format!(
+                    "watchpoint [{}]: read {} by {}: {}",
+                    addr, value, pc, instruction
+                ),
+                MemAccess::Write { addr, old, new } => format!(
+                    "watchpoint [{}]: {} -> {} by {}: {}",
+                    addr, old, new, pc, instruction
+                ),
+            })
+            .collect()
+    }
+
+    fn watch(&mut self, range: &[usize], read: bool, write: bool) -> Result<String, String> {
+        let (start, end) = watch_range(range)?;
+        self.watchpoints.push(Watchpoint {
+            start,
+            end,
+            read,
+            write,
+        });
+        self.cmp.set_access_log(true);
+        Ok(format!(
+            "watchpoint on {}",
+            describe(self.watchpoints.last().unwrap())
+        ))
+    }
+
+    fn unwatch(&mut self, range: &[usize]) -> Result<String, String> {
+        let (start, end) = watch_range(range)?;
+        let before = self.watchpoints.len();
+        self.watchpoints
+            .retain(|wp| wp.start != start || wp.end != end);
+        if self.watchpoints.is_empty() {
+            self.cmp.set_access_log(false);
+        }
+        if self.watchpoints.len() == before {
+            Ok(format!("no watchpoint on {}..={}", start, end))
+        } else {
+            Ok(format!("deleted watchpoint on {}..={}", start, end))
+        }
+    }
+
+    fn list_watchpoints(&self) -> String {
+        if self.watchpoints.is_empty() {
+            return "no watchpoints".to_owned();
+        }
+        self.watchpoints
+            .iter()
+            .map(describe)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "no breakpoints".to_owned();
+        }
+        self.breakpoints
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn regs(&self) -> String {
+        let mut text = format!(
+            "pc {}  rb {}  executed {}",
+            self.cmp.pc,
+            self.cmp.rb,
+            self.cmp.executed()
+        );
+        if self.cmp.halted {
+            text.push_str("  (halted)");
+        }
+        text
+    }
+
+    fn examine(&self, addr: usize, count: Option<usize>) -> Result<String, String> {
+        let count = count.unwrap_or(8);
+        let stop = addr
+            .checked_add(count)
+            .ok_or_else(|| format!("{} cells from {} is past the end of memory", count, addr))?;
+        let mut lines = Vec::new();
+        for start in (addr..stop).step_by(8) {
+            let end = start.saturating_add(8).min(stop);
+            let mut cells = Vec::new();
+            let mut beyond = None;
+            for a in start..end {
+                match self.cmp.mem().get(a) {
+                    Ok(value) => cells.push(value.to_string()),
+                    Err(err) => {
+                        beyond = Some(err);
+                        break;
+                    }
+                }
+            }
+            if !cells.is_empty() {
+                lines.push(format!("{:>5}: {}", start, cells.join(" ")));
+            }
+            if let Some(err) = beyond {
+                lines.push(format!(
+                    "address {} is beyond the limit {}",
+                    err.addr, err.limit
+                ));
+                break;
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn set(&mut self, args: &[&str]) -> Result<String, String> {
+        if args.len() != 2 {
+            return Err("usage: set <addr> <value>".to_owned());
+        }
+        let addr = parse_arg::<usize>(args[0])?;
+        let value = parse_arg::<Base>(args[1])?;
+        self.cmp
+            .mem_mut()
+            .set(addr, value)
+            .map_err(|err| format!("address {} is beyond the limit {}", err.addr, err.limit))?;
+        Ok(format!("{:>5}: {}", addr, value))
+    }
+
+    fn disassemble(&self, addr: usize, count: usize) -> String {
+        let mut lines = Vec::new();
+        let mut addr = addr;
+        for _ in 0..count {
+            let line = match line_at(self.cmp.mem(), addr) {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let marker = if addr == self.cmp.pc { "=>" } else { "  " };
+            lines.push(format!("{} {}", marker, line));
+            addr += line.size;
+        }
+        lines.join("\n")
+    }
+
+    fn input(&mut self, args: &[&str]) -> Result<String, String> {
+        if args.is_empty() {
+            return Err("usage: input <value>...".to_owned());
+        }
+        let values = args
+            .iter()
+            .map(|arg| parse_arg::<Base>(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        for &value in &values {
+            self.cmp.push_input(value);
+        }
+        Ok(format!("queued {} value(s)", values.len()))
+    }
+}
+
+fn describe(wp: &Watchpoint) -> String {
+    let kind = match (wp.read, wp.write) {
+        (true, true) => "read/write",
+        (true, false) => "read",
+        _ => "write",
+    };
+    format!("{}..={} ({})", wp.start, wp.end, kind)
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("invalid number `{}`", arg))
+}
+
+/// Parses between `min` and `max` address or count arguments.
+fn parse_args(args: &[&str], min: usize, max: usize) -> Result<Vec<usize>, String> {
+    if args.len() < min || args.len() > max {
+        return Err(format!("expected {} to {} arguments", min, max));
+    }
+    args.iter().map(|arg| parse_arg(arg)).collect()
+}
+
+/// Reads the `a [b]` range of a watch command, which covers `a..=b`.
+fn watch_range(range: &[usize]) -> Result<(usize, usize), String> {
+    let start = range[0];
+    let end = range.get(1).copied().unwrap_or(start);
+    if end < start {
+        return Err(format!("invalid range {}..={}", start, end));
+    }
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Reads a value and outputs it doubled, then halts.
+    const DOUBLER: [Base; 9] = [3, 9, 1002, 9, 2, 9, 4, 9, 99];
+
+    fn debugger() -> Debugger {
+        Debugger::new(Computer::new(
+            "dbg",
+            DOUBLER.to_vec(),
+            VecDeque::new(),
+            Vec::new(),
+        ))
+    }
+
+    fn text(reply: Reply) -> String {
+        match reply {
+            Reply::Text(text) => text,
+            Reply::Quit => panic!("unexpected quit"),
+        }
+    }
+
+    #[test]
+    fn test_step_and_input() {
+        let mut dbg = debugger();
+        assert_eq!(
+            text(dbg.execute("step")),
+            "waiting for input at 0; queue values with `input`"
+        );
+        text(dbg.execute("input 21"));
+        assert_eq!(text(dbg.execute("step")), "=>     2: MUL [9], #2, [9]");
+        assert_eq!(text(dbg.execute("regs")), "pc 2  rb 0  executed 1");
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut dbg = debugger();
+        dbg.execute("input 4");
+        dbg.execute("break 6");
+        assert_eq!(
+            text(dbg.execute("continue")),
+            "breakpoint at 6\n=>     6: OUT [9]"
+        );
+        assert_eq!(text(dbg.execute("continue")), "output: 8\nhalted");
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut dbg = debugger();
+        assert_eq!(text(dbg.execute("set 9 42")), "    9: 42");
+        assert_eq!(text(dbg.execute("x 8 2")), "    8: 99 42");
+        assert_eq!(
+            text(dbg.execute("x 18446744073709551615 2")),
+            "2 cells from 18446744073709551615 is past the end of memory"
+        );
+        assert_eq!(
+            text(dbg.execute("x 16777215 3")),
+            "16777215: 0\naddress 16777216 is beyond the limit 16777216"
+        );
+        assert_eq!(
+            text(dbg.execute("disas 6 3")),
+            "       6: OUT [9]\n       8: HLT\n       9: DATA 42"
+        );
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut dbg = debugger();
+        dbg.execute("input 4");
+        assert_eq!(text(dbg.execute("watch 9")), "watchpoint on 9..=9 (write)");
+        assert_eq!(
+            text(dbg.execute("continue")),
+            "watchpoint [9]: 0 -> 4 by 0: IN [9]\n=>     2: MUL [9], #2, [9]"
+        );
+        assert_eq!(
+            text(dbg.execute("continue")),
+            "watchpoint [9]: 4 -> 8 by 2: MUL [9], #2, [9]\n=>     6: OUT [9]"
+        );
+
+        dbg.execute("unwatch 9");
+        dbg.execute("rwatch 8 9");
+        assert_eq!(
+            text(dbg.execute("continue")),
+            "output: 8\nwatchpoint [9]: read 8 by 6: OUT [9]\n=>     8: HLT"
+        );
+        assert_eq!(text(dbg.execute("continue")), "halted");
+        assert_eq!(text(dbg.execute("watch 5 2")), "invalid range 5..=2");
+        assert_eq!(text(dbg.execute("unwatch 5 2")), "invalid range 5..=2");
+        assert_eq!(text(dbg.execute("watchpoints")).lines().count(), 1);
+    }
+
+    #[test]
+    fn test_watchpoint_on_faulting_step() {
+        // Reads [5], then writes to a negative address.
+        let program = vec![1, 5, 5, -1, 99, 7];
+        let mut dbg = Debugger::new(Computer::new("w", program, VecDeque::new(), Vec::new()));
+        dbg.execute("rwatch 5");
+        assert_eq!(
+            text(dbg.execute("continue")),
+            "watchpoint [5]: read 7 by 0: ADD [5], [5], [-1]\n\
+             watchpoint [5]: read 7 by 0: ADD [5], [5], [-1]\n\
+             error: negative address -1 at pc 0 (instruction 1)"
+        );
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        let mut dbg = debugger();
+        assert_eq!(
+            text(dbg.execute("jump 4")),
+            "unknown command `jump`; try `help`"
+        );
+        assert_eq!(text(dbg.execute("break x")), "invalid number `x`");
+        assert_eq!(dbg.execute("quit"), Reply::Quit);
+    }
+}
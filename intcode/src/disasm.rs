@@ -0,0 +1,161 @@
+use crate::instruction::{parse_instruction, OpCode, PMode};
+use crate::memory::{Memory, OutOfRange};
+use crate::Base;
+use std::fmt;
+
+/// One line of a disassembly listing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub addr: usize,
+    /// The number of cells the line covers.
+    pub size: usize,
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>5}: {}", self.addr, self.text)
+    }
+}
+
+/// The assembler mnemonic for an opcode.
+pub fn mnemonic(op: &OpCode) -> &'static str {
+    match op {
+        OpCode::Add { .. } => "ADD",
+        OpCode::Multiply { .. } => "MUL",
+        OpCode::Input { .. } => "IN",
+        OpCode::Output { .. } => "OUT",
+        OpCode::JmpT { .. } => "JT",
+        OpCode::JmpF { .. } => "JF",
+        OpCode::Less { .. } => "LT",
+        OpCode::Eq { .. } => "EQ",
+        OpCode::AdjustBase { .. } => "ARB",
+        OpCode::Halt => "HLT",
+    }
+}
+
+/// The parameter modes of an opcode, in operand order.
+pub fn p_modes(op: &OpCode) -> &[PMode] {
+    match op {
+        OpCode::Add { p_modes }
+        | OpCode::Multiply { p_modes }
+        | OpCode::Less { p_modes }
+        | OpCode::Eq { p_modes } => p_modes,
+        OpCode::JmpT { p_modes } | OpCode::JmpF { p_modes } => p_modes,
+        OpCode::Input { p_modes } | OpCode::Output { p_modes } | OpCode::AdjustBase { p_modes } => {
+            p_modes
+        }
+        OpCode::Halt => &[],
+    }
+}
+
+/// Formats an operand: `[5]` for position, `#5` for immediate and `rb+5`
+/// for relative mode.
+pub fn format_operand(p_mode: PMode, value: Base) -> String {
+    match p_mode {
+        PMode::Position => format!("[{}]", value),
+        PMode::Immediate => format!("#{}", value),
+        PMode::Relative if value < 0 => format!("rb{}", value),
+        PMode::Relative => format!("rb+{}", value),
+    }
+}
+
+/// Formats a decoded instruction and its raw parameters, e.g. `ADD [5], #3, rb+2`.
+pub fn format_instruction(op: &OpCode, params: &[Base]) -> String {
+    let operands: Vec<String> = p_modes(op)
+        .iter()
+        .zip(params)
+        .map(|(&p_mode, &value)| format_operand(p_mode, value))
+        .collect();
+    if operands.is_empty() {
+        mnemonic(op).to_owned()
+    } else {
+        format!("{} {}", mnemonic(op), operands.join(", "))
+    }
+}
+
+/// Disassembles the instruction at `addr`, returning its text and size.
+/// Returns `None` if the cell does not hold a valid instruction.
+pub fn disassemble_at(mem: &Memory, addr: usize) -> Option<(String, usize)> {
+    let op = parse_instruction(mem.get(addr).ok()?).ok()?;
+    let params = (1..op.size())
+        .map(|i| mem.get(addr + i).ok())
+        .collect::<Option<Vec<Base>>>()?;
+    Some((format_instruction(&op, &params), op.size()))
+}
+
+/// Disassembles the cell at `addr`, falling back to a `DATA` line if it
+/// does not hold a valid instruction.
+pub fn line_at(mem: &Memory, addr: usize) -> Result<Line, OutOfRange> {
+    let value = mem.get(addr)?;
+    let (text, size) = disassemble_at(mem, addr).unwrap_or_else(|| (format!("DATA {}", value), 1));
+    Ok(Line { addr, size, text })
+}
+
+/// Walks `program` from the start, producing one line per instruction. Cells
+/// that don't decode, including instructions cut off by the end of the
+/// program, are listed as `DATA`, one per line.
+pub fn disassemble(program: &[Base]) -> Vec<Line> {
+    let mem = Memory::new(program.to_vec());
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < program.len() {
+        let line = match line_at(&mem, addr) {
+            Ok(line) if addr + line.size <= program.len() => line,
+            _ => Line {
+                addr,
+                size: 1,
+                text: format!("DATA {}", program[addr]),
+            },
+        };
+        addr += line.size;
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_instruction() {
+        let mem = Memory::new(vec![21101, 5, -3, 2, 99]);
+        assert_eq!(
+            disassemble_at(&mem, 0),
+            Some(("ADD #5, #-3, rb+2".to_owned(), 4))
+        );
+        assert_eq!(disassemble_at(&mem, 4), Some(("HLT".to_owned(), 1)));
+    }
+
+    #[test]
+    fn test_not_an_instruction() {
+        let mem = Memory::new(vec![42]);
+        assert_eq!(disassemble_at(&mem, 0), None);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let lines: Vec<String> = disassemble(&[1002, 4, 3, 4, 33, 109, -2, 204, 1, 99, 7, 1])
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            &[
+                "    0: MUL [4], #3, [4]",
+                "    4: DATA 33",
+                "    5: ARB #-2",
+                "    7: OUT rb+1",
+                "    9: HLT",
+                "   10: DATA 7",
+                "   11: DATA 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negative_relative_operand() {
+        assert_eq!(format_operand(PMode::Relative, -1), "rb-1");
+    }
+}
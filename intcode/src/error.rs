@@ -0,0 +1,180 @@
+use crate::memory::OutOfRange;
+use crate::Base;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// A fault raised while executing an instruction. Every variant records the
+/// program counter and the raw instruction word being executed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VmError {
+    UnknownOpCode {
+        pc: usize,
+        instruction: Base,
+    },
+    UnknownPMode {
+        pc: usize,
+        instruction: Base,
+    },
+    NegativeAddress {
+        pc: usize,
+        instruction: Base,
+        addr: Base,
+    },
+    AddressOutOfRange {
+        pc: usize,
+        instruction: Base,
+        addr: usize,
+        limit: usize,
+    },
+    ImmediateWrite {
+        pc: usize,
+        instruction: Base,
+    },
+    InputClosed {
+        pc: usize,
+        instruction: Base,
+    },
+    OutputClosed {
+        pc: usize,
+        instruction: Base,
+    },
+    InstructionLimit {
+        pc: usize,
+        instruction: Base,
+        limit: u64,
+    },
+    TimeLimit {
+        pc: usize,
+        instruction: Base,
+        limit: Duration,
+    },
+}
+
+impl VmError {
+    /// The program counter of the faulting instruction.
+    pub fn pc(&self) -> usize {
+        match *self {
+            VmError::UnknownOpCode { pc, .. }
+            | VmError::UnknownPMode { pc, .. }
+            | VmError::NegativeAddress { pc, .. }
+            | VmError::AddressOutOfRange { pc, .. }
+            | VmError::ImmediateWrite { pc, .. }
+            | VmError::InputClosed { pc, .. }
+            | VmError::OutputClosed { pc, .. }
+            | VmError::InstructionLimit { pc, .. }
+            | VmError::TimeLimit { pc, .. } => pc,
+        }
+    }
+
+    /// The raw word of the faulting instruction.
+    pub fn instruction(&self) -> Base {
+        match *self {
+            VmError::UnknownOpCode { instruction, .. }
+            | VmError::UnknownPMode { instruction, .. }
+            | VmError::NegativeAddress { instruction, .. }
+            | VmError::AddressOutOfRange { instruction, .. }
+            | VmError::ImmediateWrite { instruction, .. }
+            | VmError::InputClosed { instruction, .. }
+            | VmError::OutputClosed { instruction, .. }
+            | VmError::InstructionLimit { instruction, .. }
+            | VmError::TimeLimit { instruction, .. } => instruction,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::UnknownOpCode { .. } => write!(f, "unknown opcode")?,
+            VmError::UnknownPMode { .. } => write!(f, "unknown parameter mode")?,
+            VmError::NegativeAddress { addr, .. } => write!(f, "negative address {}", addr)?,
+            VmError::AddressOutOfRange { addr, limit, .. } => {
+                write!(f, "address {} exceeds memory limit of {}", addr, limit)?
+            }
+            VmError::ImmediateWrite { .. } => write!(f, "write parameter in immediate mode")?,
+            VmError::InputClosed { .. } => write!(f, "input closed")?,
+            VmError::OutputClosed { .. } => write!(f, "output closed")?,
+            VmError::InstructionLimit { limit, .. } => {
+                write!(f, "instruction limit of {} reached", limit)?
+            }
+            VmError::TimeLimit { limit, .. } => write!(f, "time limit of {:?} reached", limit)?,
+        }
+        write!(
+            f,
+            " at pc {} (instruction {})",
+            self.pc(),
+            self.instruction()
+        )
+    }
+}
+
+impl Error for VmError {}
+
+/// A fault detected by an instruction handler, before the pc and instruction
+/// are attached.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Fault {
+    NegativeAddress(Base),
+    AddressOutOfRange(usize, usize),
+    ImmediateWrite,
+    InputClosed,
+    OutputClosed,
+    InstructionLimit(u64),
+    TimeLimit(Duration),
+}
+
+impl Fault {
+    pub(crate) fn at(self, pc: usize, instruction: Base) -> VmError {
+        match self {
+            Fault::NegativeAddress(addr) => VmError::NegativeAddress {
+                pc,
+                instruction,
+                addr,
+            },
+            Fault::AddressOutOfRange(addr, limit) => VmError::AddressOutOfRange {
+                pc,
+                instruction,
+                addr,
+                limit,
+            },
+            Fault::ImmediateWrite => VmError::ImmediateWrite { pc, instruction },
+            Fault::InputClosed => VmError::InputClosed { pc, instruction },
+            Fault::OutputClosed => VmError::OutputClosed { pc, instruction },
+            Fault::InstructionLimit(limit) => VmError::InstructionLimit {
+                pc,
+                instruction,
+                limit,
+            },
+            Fault::TimeLimit(limit) => VmError::TimeLimit {
+                pc,
+                instruction,
+                limit,
+            },
+        }
+    }
+}
+
+impl From<OutOfRange> for Fault {
+    fn from(err: OutOfRange) -> Fault {
+        Fault::AddressOutOfRange(err.addr, err.limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let err = VmError::NegativeAddress {
+            pc: 4,
+            instruction: 1201,
+            addr: -3,
+        };
+        assert_eq!(
+            err.to_string(),
+            "negative address -3 at pc 4 (instruction 1201)"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::Base;
+
+/// Decodes a single instruction word into its opcode and parameter modes.
+///
+/// Parameter modes that are not spelled out in the word default to
+/// `PMode::Position`.
+pub fn parse_instruction(input: Base) -> Result<OpCode, DecodeError> {
+    let opcode = input % 100;
+    let mut rem = input / 100;
+    let mut p_modes = Vec::<PMode>::new();
+    while rem > 0 {
+        let p_mode = match rem % 10 {
+            0 => Ok(PMode::Position),
+            1 => Ok(PMode::Immediate),
+            2 => Ok(PMode::Relative),
+            _ => Err(DecodeError::UnknownPMode),
+        }?;
+        p_modes.push(p_mode);
+        rem /= 10;
+    }
+    let mode = |i: usize| *p_modes.get(i).unwrap_or(&PMode::Position);
+
+    match opcode {
+        1 => Ok(OpCode::Add {
+            p_modes: [mode(0), mode(1), mode(2)],
+        }),
+        2 => Ok(OpCode::Multiply {
+            p_modes: [mode(0), mode(1), mode(2)],
+        }),
+        3 => Ok(OpCode::Input { p_modes: [mode(0)] }),
+        4 => Ok(OpCode::Output { p_modes: [mode(0)] }),
+        5 => Ok(OpCode::JmpT {
+            p_modes: [mode(0), mode(1)],
+        }),
+        6 => Ok(OpCode::JmpF {
+            p_modes: [mode(0), mode(1)],
+        }),
+        7 => Ok(OpCode::Less {
+            p_modes: [mode(0), mode(1), mode(2)],
+        }),
+        8 => Ok(OpCode::Eq {
+            p_modes: [mode(0), mode(1), mode(2)],
+        }),
+        9 => Ok(OpCode::AdjustBase { p_modes: [mode(0)] }),
+        99 => Ok(OpCode::Halt),
+        _ => Err(DecodeError::UnknownOpCode),
+    }
+}
+
+/// Why an instruction word failed to decode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    UnknownOpCode,
+    UnknownPMode,
+}
+
+/// A decoded instruction. Each variant carries the modes of its parameters,
+/// including the one it writes to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OpCode {
+    Add { p_modes: [PMode; 3] },
+    Multiply { p_modes: [PMode; 3] },
+    Input { p_modes: [PMode; 1] },
+    Output { p_modes: [PMode; 1] },
+    JmpT { p_modes: [PMode; 2] },
+    JmpF { p_modes: [PMode; 2] },
+    Less { p_modes: [PMode; 3] },
+    Eq { p_modes: [PMode; 3] },
+    AdjustBase { p_modes: [PMode; 1] },
+    Halt,
+}
+
+impl OpCode {
+    /// The number of cells the instruction occupies, including parameters.
+    pub fn size(&self) -> usize {
+        match self {
+            OpCode::Add { .. }
+            | OpCode::Multiply { .. }
+            | OpCode::Less { .. }
+            | OpCode::Eq { .. } => 4,
+            OpCode::JmpT { .. } | OpCode::JmpF { .. } => 3,
+            OpCode::Input { .. } | OpCode::Output { .. } | OpCode::AdjustBase { .. } => 2,
+            OpCode::Halt => 1,
+        }
+    }
+}
+
+/// How a parameter is interpreted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PMode {
+    /// The parameter is the address of the value.
+    Position,
+    /// The parameter is the value itself.
+    Immediate,
+    /// The parameter is an address offset from the relative base.
+    Relative,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmd() {
+        let expected = Ok(OpCode::Add {
+            p_modes: [PMode::Position, PMode::Position, PMode::Position],
+        });
+        assert_eq!(parse_instruction(1), expected);
+    }
+
+    #[test]
+    fn test_parse_cmd_modes() {
+        let expected = Ok(OpCode::Multiply {
+            p_modes: [PMode::Position, PMode::Immediate, PMode::Position],
+        });
+        assert_eq!(parse_instruction(1002), expected);
+    }
+
+    #[test]
+    fn test_parse_cmd_relative() {
+        let expected = Ok(OpCode::Add {
+            p_modes: [PMode::Immediate, PMode::Immediate, PMode::Relative],
+        });
+        assert_eq!(parse_instruction(21101), expected);
+    }
+
+    #[test]
+    fn test_parse_adjust_base() {
+        let expected = Ok(OpCode::AdjustBase {
+            p_modes: [PMode::Immediate],
+        });
+        assert_eq!(parse_instruction(109), expected);
+    }
+
+    #[test]
+    fn test_parse_unknown_mode() {
+        assert_eq!(parse_instruction(301), Err(DecodeError::UnknownPMode));
+        assert_eq!(parse_instruction(42), Err(DecodeError::UnknownOpCode));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::Base;
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Instant;
+
+/// Returned by an `Output` that can no longer accept values.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Closed;
+
+/// A source of values for input instructions.
+pub trait Input {
+    /// Returns the next value, or `None` if there is nothing to read. Blocking
+    /// sources only return `None` once they are closed.
+    fn read(&mut self) -> Option<Base>;
+
+    /// Like `read`, but a blocking source gives up and returns `None` at
+    /// `deadline`. Non-blocking sources can rely on the default.
+    fn read_before(&mut self, deadline: Instant) -> Option<Base> {
+        let _ = deadline;
+        self.read()
+    }
+
+    /// Values already queued in the source that a snapshot should carry.
+    fn buffered(&self) -> Vec<Base> {
+        Vec::new()
+    }
+}
+
+/// A destination for values written by output instructions.
+pub trait Output {
+    fn write(&mut self, value: Base) -> Result<(), Closed>;
+
+    /// Values held in the sink that a snapshot should carry.
+    fn buffered(&self) -> Vec<Base> {
+        Vec::new()
+    }
+}
+
+impl<F> Input for F
+where
+    F: FnMut() -> Option<Base>,
+{
+    fn read(&mut self) -> Option<Base> {
+        self()
+    }
+}
+
+impl<F> Output for F
+where
+    F: FnMut(Base) -> Result<(), Closed>,
+{
+    fn write(&mut self, value: Base) -> Result<(), Closed> {
+        self(value)
+    }
+}
+
+impl Input for VecDeque<Base> {
+    fn read(&mut self) -> Option<Base> {
+        self.pop_front()
+    }
+
+    fn buffered(&self) -> Vec<Base> {
+        self.iter().copied().collect()
+    }
+}
+
+impl Output for Vec<Base> {
+    fn write(&mut self, value: Base) -> Result<(), Closed> {
+        self.push(value);
+        Ok(())
+    }
+
+    fn buffered(&self) -> Vec<Base> {
+        self.clone()
+    }
+}
+
+impl Output for VecDeque<Base> {
+    fn write(&mut self, value: Base) -> Result<(), Closed> {
+        self.push_back(value);
+        Ok(())
+    }
+
+    fn buffered(&self) -> Vec<Base> {
+        self.iter().copied().collect()
+    }
+}
+
+/// Reads values from any iterator, such as a fixed list of settings.
+pub struct IterInput<I>(pub I);
+
+impl<I> Input for IterInput<I>
+where
+    I: Iterator<Item = Base>,
+{
+    fn read(&mut self) -> Option<Base> {
+        self.0.next()
+    }
+}
+
+/// Blocks until a value arrives. Returns `None` once every sender is gone.
+impl Input for Receiver<Base> {
+    fn read(&mut self) -> Option<Base> {
+        self.recv().ok()
+    }
+
+    fn read_before(&mut self, deadline: Instant) -> Option<Base> {
+        recv_before(self, deadline)
+    }
+}
+
+impl Input for &Receiver<Base> {
+    fn read(&mut self) -> Option<Base> {
+        self.recv().ok()
+    }
+
+    fn read_before(&mut self, deadline: Instant) -> Option<Base> {
+        recv_before(self, deadline)
+    }
+}
+
+fn recv_before(rx: &Receiver<Base>, deadline: Instant) -> Option<Base> {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    rx.recv_timeout(timeout).ok()
+}
+
+impl Output for Sender<Base> {
+    fn write(&mut self, value: Base) -> Result<(), Closed> {
+        self.send(value).map_err(|_| Closed)
+    }
+}
+
+impl Output for &Sender<Base> {
+    fn write(&mut self, value: Base) -> Result<(), Closed> {
+        self.send(value).map_err(|_| Closed)
+    }
+}
+
+/// Reads one value per line from stdin, printing `prompt` first. End of file
+/// closes the input.
+pub struct StdinInput {
+    pub prompt: String,
+}
+
+impl StdinInput {
+    pub fn new(prompt: &str) -> StdinInput {
+        StdinInput {
+            prompt: prompt.to_owned(),
+        }
+    }
+}
+
+impl Input for StdinInput {
+    fn read(&mut self) -> Option<Base> {
+        print!("{}", self.prompt);
+        io::stdout().flush().expect("Error during flush.");
+        let mut input = String::new();
+        let read = io::stdin()
+            .read_line(&mut input)
+            .expect("Failed to read input");
+        if read == 0 {
+            return None;
+        }
+
+        Some(input.trim().parse().expect("Failed to parse input."))
+    }
+}
+
+/// Prints each value on its own line after `prefix`.
+pub struct StdoutOutput {
+    pub prefix: String,
+}
+
+impl StdoutOutput {
+    pub fn new(prefix: &str) -> StdoutOutput {
+        StdoutOutput {
+            prefix: prefix.to_owned(),
+        }
+    }
+}
+
+impl Output for StdoutOutput {
+    fn write(&mut self, value: Base) -> Result<(), Closed> {
+        println!("{}{}", self.prefix, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_iter_input() {
+        let mut input = IterInput(vec![4, 5].into_iter());
+        assert_eq!(input.read(), Some(4));
+        assert_eq!(input.read(), Some(5));
+        assert_eq!(input.read(), None);
+    }
+
+    #[test]
+    fn test_channel_closed() {
+        let (tx, rx) = channel();
+        tx.send(1).unwrap();
+        drop(tx);
+        let mut input = &rx;
+        assert_eq!(input.read(), Some(1));
+        assert_eq!(input.read(), None);
+
+        let (mut tx, rx) = channel();
+        drop(rx);
+        assert_eq!(tx.write(1), Err(Closed));
+    }
+}
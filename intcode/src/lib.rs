@@ -0,0 +1,64 @@
+//! The Intcode interpreter shared by the day2, day5 and day7 solutions.
+
+mod asm;
+mod cache;
+mod compiler;
+mod computer;
+mod debugger;
+mod disasm;
+mod error;
+mod instruction;
+mod io;
+mod limits;
+mod macros;
+mod memory;
+mod profile;
+mod snapshot;
+mod trace;
+mod tracediff;
+mod watch;
+
+pub use crate::asm::{assemble, to_mem_file, AsmError};
+pub use crate::compiler::{compile, compile_to_asm, CompileError};
+pub use crate::computer::{Computer, RunStatus, StepOutcome};
+pub use crate::debugger::{Debugger, Reply};
+pub use crate::disasm::{
+    disassemble, disassemble_at, format_instruction, format_operand, line_at, mnemonic, Line,
+};
+pub use crate::error::VmError;
+pub use crate::instruction::{parse_instruction, DecodeError, OpCode, PMode};
+pub use crate::io::{Closed, Input, IterInput, Output, StdinInput, StdoutOutput};
+pub use crate::limits::Limits;
+pub use crate::memory::{Memory, OutOfRange, DEFAULT_LIMIT, PAGE_SIZE};
+pub use crate::profile::{JumpCounts, LoopRegion, Profile};
+pub use crate::snapshot::{Snapshot, SnapshotError, FORMAT_VERSION};
+pub use crate::trace::{
+    trace_file, trace_path, BinaryTracer, JsonTracer, MemWrite, Trace, TraceError, TraceFormat,
+    TraceRecord, Tracer,
+};
+pub use crate::tracediff::{divergence_report, first_divergence, format_record, Divergence};
+pub use crate::watch::{MemAccess, Watchpoint};
+
+/// The word size of Intcode memory.
+pub type Base = i64;
+
+/// Parses a comma-separated Intcode program.
+pub fn parse_mem_file(input: &str) -> Vec<Base> {
+    // Split the input on commas
+    input
+        .split(',')
+        .map(|s| s.trim())
+        .map(|s| s.parse().expect("Not a number."))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let res = parse_mem_file("1,0,0,0,99");
+        assert_eq!(res, &[1, 0, 0, 0, 99]);
+    }
+}
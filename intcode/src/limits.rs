@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// Caps on how long a machine may run. A machine that reaches either limit
+/// stops with `VmError::InstructionLimit` or `VmError::TimeLimit`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// The most instructions the machine may execute in total.
+    pub max_instructions: Option<u64>,
+    /// The longest the machine may run, measured from its first step after
+    /// the limits were set. Time spent waiting for input counts.
+    pub max_duration: Option<Duration>,
+}
+
+impl Limits {
+    pub fn none() -> Limits {
+        Limits::default()
+    }
+
+    pub fn instructions(max: u64) -> Limits {
+        Limits {
+            max_instructions: Some(max),
+            ..Limits::default()
+        }
+    }
+
+    pub fn duration(max: Duration) -> Limits {
+        Limits {
+            max_duration: Some(max),
+            ..Limits::default()
+        }
+    }
+}
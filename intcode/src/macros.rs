@@ -0,0 +1,360 @@
+//! The assembler's macro layer.
+//!
+//! A macro is defined with
+//!
+//! ```text
+//! MACRO name param1, param2
+//!         ...body, using %param1 and %param2...
+//! ENDM
+//! ```
+//!
+//! and invoked like an instruction: `name arg1, arg2`. Arguments are
+//! substituted as text. Labels in the body that start with `.` are local to
+//! each expansion, so a macro can be used many times without its labels
+//! clashing. Bodies may invoke other macros.
+
+use crate::asm::{check_name, split_args, split_label, strip_comment, AsmError};
+use std::collections::HashMap;
+
+const STDLIB: &str = include_str!("stdlib.asm");
+
+// How deeply macros may invoke one another before we assume recursion.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Debug)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+// Keyed by lowercase name, as macro names are case-insensitive like
+// mnemonics.
+type Macros = HashMap<String, Macro>;
+
+/// Collects macro definitions from `source` and expands every invocation,
+/// returning plain assembly lines tagged with the source line they came
+/// from. Macros defined in `source` take precedence over the standard
+/// library.
+pub(crate) fn preprocess(source: &str) -> Result<Vec<(usize, String)>, AsmError> {
+    let mut stdlib = Macros::new();
+    define(STDLIB, &mut stdlib)?;
+    let mut user = Macros::new();
+    let lines = define(source, &mut user)?;
+
+    let mut expander = Expander {
+        user: &user,
+        stdlib: &stdlib,
+        expansions: 0,
+        out: Vec::new(),
+    };
+    for (line, text) in &lines {
+        expander.expand(*line, text, 0)?;
+    }
+    Ok(expander.out)
+}
+
+/// Moves macro definitions out of `source` into `macros` and returns the
+/// remaining lines.
+fn define(source: &str, macros: &mut Macros) -> Result<Vec<(usize, String)>, AsmError> {
+    let mut rest = Vec::new();
+    let mut current: Option<(usize, String, Macro)> = None;
+
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let err = |message: String| AsmError { line, message };
+        let code = strip_comment(text).trim();
+        let (word, args) = split_word(code);
+
+        match (current.as_mut(), word.to_ascii_uppercase().as_str()) {
+            (None, "MACRO") => {
+                let (name, params) = split_word(args);
+                check_name(name).map_err(|_| err(format!("invalid macro name `{}`", name)))?;
+                let params = split_args(params).map_err(err)?;
+                for param in &params {
+                    check_name(param).map_err(|_| err(format!("invalid parameter `{}`", param)))?;
+                }
+                let key = name.to_ascii_lowercase();
+                if macros.contains_key(&key) {
+                    return Err(err(format!("macro `{}` is already defined", name)));
+                }
+                let params = params.iter().map(|p| p.to_string()).collect();
+                current = Some((
+                    line,
+                    key,
+                    Macro {
+                        params,
+                        body: Vec::new(),
+                    },
+                ));
+            }
+            (Some(_), "MACRO") => {
+                return Err(err("macros cannot be defined inside macros".to_owned()))
+            }
+            (Some(_), "ENDM") => {
+                if let Some((_, name, mac)) = current.take() {
+                    macros.insert(name, mac);
+                }
+            }
+            (None, "ENDM") => return Err(err("ENDM without MACRO".to_owned())),
+            (Some((_, _, mac)), _) => mac.body.push(code.to_owned()),
+            (None, _) => rest.push((line, code.to_owned())),
+        }
+    }
+
+    if let Some((line, name, _)) = current {
+        return Err(AsmError {
+            line,
+            message: format!("macro `{}` is missing ENDM", name),
+        });
+    }
+    Ok(rest)
+}
+
+struct Expander<'a> {
+    user: &'a Macros,
+    stdlib: &'a Macros,
+    expansions: usize,
+    out: Vec<(usize, String)>,
+}
+
+impl<'a> Expander<'a> {
+    /// Expands one line. Lines produced by a macro keep the line number of
+    /// the invocation.
+    fn expand(&mut self, line: usize, text: &str, depth: usize) -> Result<(), AsmError> {
+        let err = |message: String| AsmError { line, message };
+        let mut rest = text.trim();
+        while let Some((label, after)) = split_label(rest) {
+            self.out.push((line, format!("{}:", label)));
+            rest = after.trim();
+        }
+
+        let (name, args) = split_word(rest);
+        let key = name.to_ascii_lowercase();
+        let mac = match self.user.get(&key).or_else(|| self.stdlib.get(&key)) {
+            Some(mac) => mac,
+            None => {
+                if !rest.is_empty() {
+                    self.out.push((line, rest.to_owned()));
+                }
+                return Ok(());
+            }
+        };
+
+        if depth >= MAX_DEPTH {
+            return Err(err(format!("macro `{}` nests too deeply", name)));
+        }
+        let args = split_args(args).map_err(err)?;
+        if args.len() != mac.params.len() {
+            return Err(err(format!(
+                "macro `{}` takes {} argument(s), found {}",
+                name,
+                mac.params.len(),
+                args.len()
+            )));
+        }
+
+        self.expansions += 1;
+        let tag = format!("{}${}", key, self.expansions);
+        for body in &mac.body {
+            // Rename locals before substituting so that arguments are left
+            // alone.
+            let text = substitute(&rename_locals(body, &tag), &mac.params, &args)
+                .map_err(|message| err(format!("in macro `{}`: {}", name, message)))?;
+            self.expand(line, &text, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(idx) => (&text[..idx], text[idx..].trim()),
+        None => (text, ""),
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+/// Prefixes every `.label` outside strings with `tag`.
+fn rename_locals(text: &str, tag: &str) -> String {
+    let mut out = String::new();
+    let mut in_string = false;
+    let mut prev = ' ';
+    for c in text.chars() {
+        if c == '"' {
+            in_string = !in_string;
+        }
+        if c == '.' && !in_string && !is_name_char(prev) {
+            out.push_str(tag);
+        }
+        out.push(c);
+        prev = c;
+    }
+    out
+}
+
+/// Replaces each `%param` outside strings with its argument.
+fn substitute(text: &str, params: &[String], args: &[&str]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut in_string = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            in_string = !in_string;
+        }
+        if c != '%' || in_string {
+            out.push(c);
+            continue;
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if !is_name_char(c) {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        match params.iter().position(|p| *p == name) {
+            Some(idx) => out.push_str(args[idx]),
+            None => return Err(format!("unknown parameter `%{}`", name)),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::computer::Computer;
+    use crate::Base;
+    use std::collections::VecDeque;
+
+    fn run(source: &str) -> Vec<Base> {
+        let program = assemble(source).unwrap();
+        let mut cmp = Computer::new("macros", program, VecDeque::new(), Vec::new());
+        cmp.compute().unwrap();
+        cmp.output().clone()
+    }
+
+    fn error(source: &str) -> AsmError {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn test_hygienic_labels() {
+        let source = "
+            MACRO twice x
+                    mov #2, [.n]
+            .again: OUT %x
+                    djnz [.n], #.again
+                    jmp #.end
+            .n:     DATA 0
+            .end:
+            ENDM
+
+                    twice #1
+                    twice #2
+                    HLT
+        ";
+        assert_eq!(run(source), &[1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let source = "
+                    stack_init #stack
+                    push #3
+                    push #4
+                    pop [a]
+                    pop [b]
+                    OUT [a]
+                    OUT [b]
+                    HLT
+            a:      DATA 0
+            b:      DATA 0
+            stack:  ZERO 4
+        ";
+        assert_eq!(run(source), &[4, 3]);
+    }
+
+    #[test]
+    fn test_call_ret() {
+        let source = "
+                    stack_init #stack
+                    push #21
+                    call #double
+                    call #double
+                    pop [r]
+                    OUT [r]
+                    HLT
+            ; Doubles its argument in place.
+            double: MUL rb-2, #2, rb-2
+                    ret
+            r:      DATA 0
+            stack:  ZERO 8
+        ";
+        assert_eq!(run(source), &[84]);
+    }
+
+    #[test]
+    fn test_loop() {
+        let source = "
+            loop:   ADD [sum], [n], [sum]
+                    djnz [n], #loop
+                    OUT [sum]
+                    HLT
+            n:      DATA 5
+            sum:    DATA 0
+        ";
+        assert_eq!(run(source), &[15]);
+    }
+
+    #[test]
+    fn test_print() {
+        let source = "
+                    print hi
+                    print hi
+                    HLT
+            hi:     DATA \"hi\\n\", 0
+        ";
+        assert_eq!(run(source), &[104, 105, 10, 104, 105, 10]);
+    }
+
+    #[test]
+    fn test_user_macro_shadows_stdlib() {
+        let source = "
+            MACRO inc x
+                    ADD %x, #10, %x
+            ENDM
+                    inc [v]
+                    OUT [v]
+                    HLT
+            v:      DATA 1
+        ";
+        assert_eq!(run(source), &[11]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error("HLT\nMACRO m\nHLT").to_string(),
+            "line 2: macro `m` is missing ENDM"
+        );
+        assert_eq!(
+            error("HLT\npush #1, #2").to_string(),
+            "line 2: macro `push` takes 1 argument(s), found 2"
+        );
+        assert_eq!(
+            error("MACRO m\nOUT %y\nENDM\nm").to_string(),
+            "line 4: in macro `m`: unknown parameter `%y`"
+        );
+        assert_eq!(
+            error("MACRO m\nm\nENDM\nm").to_string(),
+            "line 4: macro `m` nests too deeply"
+        );
+        assert_eq!(error("ENDM").to_string(), "line 1: ENDM without MACRO");
+    }
+}
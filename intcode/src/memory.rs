@@ -0,0 +1,186 @@
+use crate::Base;
+use std::sync::Arc;
+
+/// The default ceiling on addressable cells (128 MiB of `Base` words).
+pub const DEFAULT_LIMIT: usize = 1 << 24;
+
+/// The number of cells in a memory page.
+pub const PAGE_SIZE: usize = 1024;
+
+/// Returned when an access falls at or beyond the memory limit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutOfRange {
+    pub addr: usize,
+    pub limit: usize,
+}
+
+/// Intcode memory. Addresses beyond the loaded program read as zero, and
+/// writing to one grows the memory to cover it, as long as the address is
+/// below the configured limit.
+///
+/// Cells are stored in pages that are shared between clones and only copied
+/// when one side writes to them, so cloning memory is cheap.
+#[derive(Clone, Debug)]
+pub struct Memory {
+    // `None` pages have never been written and read as zero.
+    pages: Vec<Option<Arc<Vec<Base>>>>,
+    len: usize,
+    limit: usize,
+}
+
+impl Memory {
+    /// Creates memory holding `cells` with the default limit.
+    pub fn new(cells: Vec<Base>) -> Memory {
+        Memory::with_limit(cells, DEFAULT_LIMIT)
+    }
+
+    /// Creates memory holding `cells` that may grow up to `limit` cells.
+    pub fn with_limit(cells: Vec<Base>, limit: usize) -> Memory {
+        let pages = cells
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = chunk.to_vec();
+                page.resize(PAGE_SIZE, 0);
+                Some(Arc::new(page))
+            })
+            .collect();
+
+        Memory {
+            pages,
+            len: cells.len(),
+            limit,
+        }
+    }
+
+    /// The number of addressable cells.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Reads the cell at `addr`. Cells that were never written read as zero.
+    pub fn get(&self, addr: usize) -> Result<Base, OutOfRange> {
+        self.check(addr)?;
+        match self.pages.get(addr / PAGE_SIZE) {
+            Some(Some(page)) => Ok(page[addr % PAGE_SIZE]),
+            _ => Ok(0),
+        }
+    }
+
+    /// Writes the cell at `addr`, zero-filling any gap past the current end.
+    /// A page shared with a clone is copied before it is modified.
+    pub fn set(&mut self, addr: usize, value: Base) -> Result<(), OutOfRange> {
+        self.check(addr)?;
+        let page_idx = addr / PAGE_SIZE;
+        if page_idx >= self.pages.len() {
+            self.pages.resize(page_idx + 1, None);
+        }
+        let page = self.pages[page_idx].get_or_insert_with(|| Arc::new(vec![0; PAGE_SIZE]));
+        Arc::make_mut(page)[addr % PAGE_SIZE] = value;
+        if addr >= self.len {
+            self.len = addr + 1;
+        }
+        Ok(())
+    }
+
+    /// The number of cells up to and including the highest one loaded or
+    /// written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies out the first `len()` cells.
+    pub fn to_vec(&self) -> Vec<Base> {
+        (0..self.len)
+            .map(|addr| self.get(addr).unwrap_or(0))
+            .collect()
+    }
+
+    /// The number of pages this memory shares with `other`.
+    pub fn shared_pages(&self, other: &Memory) -> usize {
+        self.pages
+            .iter()
+            .zip(other.pages.iter())
+            .filter(|(a, b)| match (a, b) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                _ => false,
+            })
+            .count()
+    }
+
+    fn check(&self, addr: usize) -> Result<(), OutOfRange> {
+        if addr >= self.limit {
+            return Err(OutOfRange {
+                addr,
+                limit: self.limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Memory {
+    fn eq(&self, other: &Memory) -> bool {
+        self.len == other.len && self.limit == other.limit && self.to_vec() == other.to_vec()
+    }
+}
+
+impl From<Vec<Base>> for Memory {
+    fn from(cells: Vec<Base>) -> Memory {
+        Memory::new(cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_past_end() {
+        let mem = Memory::new(vec![1, 2, 3]);
+        assert_eq!(mem.get(1000), Ok(0));
+        assert_eq!(mem.len(), 3);
+    }
+
+    #[test]
+    fn test_write_past_end() {
+        let mut mem = Memory::new(vec![1, 2, 3]);
+        mem.set(5, 9).unwrap();
+        assert_eq!(mem.to_vec(), &[1, 2, 3, 0, 0, 9]);
+    }
+
+    #[test]
+    fn test_write_far_past_end() {
+        let mut mem = Memory::new(vec![1, 2, 3]);
+        mem.set(PAGE_SIZE * 10 + 3, 9).unwrap();
+        assert_eq!(mem.get(PAGE_SIZE * 10 + 3), Ok(9));
+        assert_eq!(mem.get(PAGE_SIZE * 5), Ok(0));
+        assert_eq!(mem.len(), PAGE_SIZE * 10 + 4);
+    }
+
+    #[test]
+    fn test_write_past_limit() {
+        let mut mem = Memory::with_limit(vec![1, 2, 3], 8);
+        assert_eq!(mem.set(8, 1), Err(OutOfRange { addr: 8, limit: 8 }));
+        assert_eq!(mem.len(), 3);
+    }
+
+    #[test]
+    fn test_clone_copies_on_write() {
+        let mut mem = Memory::new(vec![7; PAGE_SIZE * 3]);
+        let copy = mem.clone();
+        assert_eq!(mem.shared_pages(&copy), 3);
+
+        mem.set(PAGE_SIZE + 1, 1).unwrap();
+        assert_eq!(mem.shared_pages(&copy), 2);
+        assert_eq!(mem.get(PAGE_SIZE + 1), Ok(1));
+        assert_eq!(copy.get(PAGE_SIZE + 1), Ok(7));
+    }
+}
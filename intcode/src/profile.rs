@@ -0,0 +1,270 @@
+use crate::disasm::{line_at, mnemonic};
+use crate::instruction::{OpCode, PMode};
+use crate::memory::{Memory, PAGE_SIZE};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// How often a conditional jump went each way.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct JumpCounts {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// A loop found from a backward jump: the code from `start` up to and
+/// including the jump at `end`. Only jumps to an immediate address count,
+/// so that returns through a stored address don't look like loops.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoopRegion {
+    pub start: usize,
+    pub end: usize,
+    /// How many times the jump went back to `start`.
+    pub iterations: u64,
+}
+
+impl LoopRegion {
+    pub fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr <= self.end
+    }
+}
+
+/// Execution counts collected by a machine with profiling turned on.
+///
+/// A jump whose target is the next instruction is counted as not taken,
+/// since the two can't be told apart.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    // Execution counts by address, in pages like `Memory`. `None` pages
+    // were never executed.
+    counts: Vec<Option<Box<[u64]>>>,
+    opcodes: BTreeMap<&'static str, u64>,
+    jumps: BTreeMap<usize, JumpCounts>,
+    back_edges: BTreeMap<(usize, usize), u64>,
+}
+
+impl Profile {
+    /// Counts one execution of `op` at `pc`, after which the program
+    /// counter was `next`.
+    pub(crate) fn record(&mut self, pc: usize, op: &OpCode, next: usize) {
+        let page_idx = pc / PAGE_SIZE;
+        if page_idx >= self.counts.len() {
+            self.counts.resize(page_idx + 1, None);
+        }
+        let page =
+            self.counts[page_idx].get_or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+        page[pc % PAGE_SIZE] += 1;
+        *self.opcodes.entry(mnemonic(op)).or_insert(0) += 1;
+
+        if let OpCode::JmpT { p_modes } | OpCode::JmpF { p_modes } = op {
+            let jump = self.jumps.entry(pc).or_default();
+            if next == pc + op.size() {
+                jump.not_taken += 1;
+            } else {
+                jump.taken += 1;
+                if next <= pc && p_modes[1] == PMode::Immediate {
+                    *self.back_edges.entry((next, pc)).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    /// The total number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.counts
+            .iter()
+            .flatten()
+            .flat_map(|page| page.iter())
+            .sum()
+    }
+
+    /// How many times the instruction at `addr` was executed.
+    pub fn count(&self, addr: usize) -> u64 {
+        match self.counts.get(addr / PAGE_SIZE) {
+            Some(Some(page)) => page[addr % PAGE_SIZE],
+            _ => 0,
+        }
+    }
+
+    /// How many times each opcode was executed, most frequent first.
+    pub fn opcodes(&self) -> Vec<(&'static str, u64)> {
+        let mut opcodes: Vec<_> = self.opcodes.iter().map(|(&op, &n)| (op, n)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        opcodes
+    }
+
+    /// Taken and not-taken counts for each conditional jump, by address.
+    pub fn jumps(&self) -> &BTreeMap<usize, JumpCounts> {
+        &self.jumps
+    }
+
+    /// Executed addresses, most executed first.
+    pub fn hot_spots(&self) -> Vec<(usize, u64)> {
+        let mut hot: Vec<_> = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, page)| page.as_ref().map(|page| (i * PAGE_SIZE, page)))
+            .flat_map(|(base, page)| page.iter().enumerate().map(move |(j, &n)| (base + j, n)))
+            .filter(|&(_, n)| n > 0)
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot
+    }
+
+    /// The loops taken backward jumps formed, outermost first.
+    pub fn loops(&self) -> Vec<LoopRegion> {
+        let mut loops: Vec<_> = self
+            .back_edges
+            .iter()
+            .map(|(&(start, end), &iterations)| LoopRegion {
+                start,
+                end,
+                iterations,
+            })
+            .collect();
+        loops.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+        loops
+    }
+
+    /// Formats the `top` hottest addresses with their disassembly and the
+    /// innermost loop each belongs to, followed by opcode, jump and loop
+    /// summaries. Instructions are disassembled from `mem` as it is now.
+    pub fn report(&self, mem: &Memory, top: usize) -> String {
+        let total = self.total();
+        let percent = |n: u64| 100.0 * n as f64 / total.max(1) as f64;
+        let loops = self.loops();
+        let mut report = format!("{} instructions executed\n\nhot spots:\n", total);
+
+        for (addr, n) in self.hot_spots().into_iter().take(top) {
+            let text = match line_at(mem, addr) {
+                Ok(line) => line.text,
+                Err(_) => "??".to_owned(),
+            };
+            let mut line = format!("{:>10} {:>6.2}%  {:>5}: {:<24}", n, percent(n), addr, text);
+            let innermost = loops
+                .iter()
+                .filter(|l| l.contains(addr))
+                .min_by_key(|l| l.end - l.start);
+            if let Some(l) = innermost {
+                write!(line, "  loop {}..{}", l.start, l.end).unwrap();
+            }
+            writeln!(report, "{}", line.trim_end()).unwrap();
+        }
+
+        report.push_str("\nopcodes:\n");
+        for (op, n) in self.opcodes() {
+            writeln!(report, "  {:<4}{:>10} {:>6.2}%", op, n, percent(n)).unwrap();
+        }
+
+        if !self.jumps.is_empty() {
+            report.push_str("\njumps:          taken  not taken\n");
+            for (addr, jump) in &self.jumps {
+                writeln!(
+                    report,
+                    "  {:>5}: {:>10} {:>10}",
+                    addr, jump.taken, jump.not_taken
+                )
+                .unwrap();
+            }
+        }
+
+        if !loops.is_empty() {
+            report.push_str("\nloops:\n");
+            for l in &loops {
+                let executed: u64 = (l.start..=l.end).map(|addr| self.count(addr)).sum();
+                writeln!(
+                    report,
+                    "  {:>5}..{:<5} {:>10} iterations {:>6.2}% of instructions",
+                    l.start,
+                    l.end,
+                    l.iterations,
+                    percent(executed)
+                )
+                .unwrap();
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::computer::Computer;
+    use crate::Base;
+    use std::collections::VecDeque;
+
+    // Counts down from its input, outputting each value.
+    const COUNTDOWN: [Base; 13] = [3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0];
+
+    fn profiled(n: Base) -> Computer {
+        let mut cmp = Computer::new("p", COUNTDOWN.to_vec(), VecDeque::from(vec![n]), Vec::new());
+        cmp.set_profiling(true);
+        cmp.compute().unwrap();
+        cmp
+    }
+
+    #[test]
+    fn test_counts() {
+        let cmp = profiled(3);
+        let profile = cmp.profile().unwrap();
+        assert_eq!(profile.total(), 1 + 3 * 3 + 1);
+        assert_eq!(profile.count(0), 1);
+        assert_eq!(profile.count(2), 3);
+        assert_eq!(profile.count(1), 0);
+        assert_eq!(profile.hot_spots()[0], (2, 3));
+        assert_eq!(
+            profile.opcodes(),
+            &[("ADD", 3), ("JT", 3), ("OUT", 3), ("HLT", 1), ("IN", 1)]
+        );
+        assert_eq!(profile.jumps()[&8].taken, 2);
+        assert_eq!(profile.jumps()[&8].not_taken, 1);
+    }
+
+    #[test]
+    fn test_loops() {
+        let cmp = profiled(5);
+        let loops = cmp.profile().unwrap().loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!((loops[0].start, loops[0].end), (2, 8));
+        assert_eq!(loops[0].iterations, 4);
+    }
+
+    #[test]
+    fn test_report() {
+        let cmp = profiled(2);
+        let report = cmp.profile().unwrap().report(cmp.mem(), 2);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "8 instructions executed");
+        assert_eq!(
+            lines[3],
+            "         2  25.00%      2: OUT [12]                  loop 2..8"
+        );
+        assert_eq!(
+            lines[4],
+            "         2  25.00%      4: ADD [12], #-1, [12]       loop 2..8"
+        );
+        assert!(lines.contains(&"      8:          1          1"));
+        assert!(lines.contains(&"      2..8              1 iterations  75.00% of instructions"));
+    }
+
+    #[test]
+    fn test_high_address() {
+        // Writes HLT far past the program and jumps to it.
+        let program = vec![1101, 99, 0, 16_000_000, 1105, 1, 16_000_000];
+        let mut cmp = Computer::new("p", program, VecDeque::new(), Vec::new());
+        cmp.set_profiling(true);
+        cmp.compute().unwrap();
+        let profile = cmp.profile().unwrap();
+        assert_eq!(profile.total(), 3);
+        assert_eq!(profile.count(16_000_000), 1);
+        assert_eq!(profile.hot_spots(), &[(0, 1), (4, 1), (16_000_000, 1)]);
+        assert_eq!(profile.counts.iter().flatten().count(), 2);
+    }
+
+    #[test]
+    fn test_off_by_default() {
+        let mut cmp = Computer::new("p", COUNTDOWN.to_vec(), VecDeque::from(vec![1]), Vec::new());
+        cmp.compute().unwrap();
+        assert!(cmp.profile().is_none());
+    }
+}
@@ -0,0 +1,348 @@
+use crate::computer::Computer;
+use crate::io::{Closed, Input, Output};
+use crate::memory::Memory;
+use crate::Base;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &str = "INTCODE-SNAPSHOT";
+
+/// The on-disk format version written by `Snapshot::write_to`.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The complete state of a `Computer`, detached from its input and output.
+///
+/// On disk a snapshot is a line-oriented text file: a `INTCODE-SNAPSHOT <version>`
+/// header followed by one `key value` line per field. Lists are
+/// comma-separated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    pub pc: usize,
+    pub rb: Base,
+    pub halted: bool,
+    pub limit: usize,
+    pub mem: Vec<Base>,
+    /// Input queued on the machine or buffered in its source, in read order.
+    pub pending_input: Vec<Base>,
+    /// Output buffered in the machine's sink.
+    pub pending_output: Vec<Base>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadHeader,
+    UnsupportedVersion(u32),
+    MissingField(&'static str),
+    InvalidField { line: usize, message: String },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{}", err),
+            SnapshotError::BadHeader => write!(f, "not an intcode snapshot"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported snapshot version {}", v)
+            }
+            SnapshotError::MissingField(field) => write!(f, "missing field `{}`", field),
+            SnapshotError::InvalidField { line, message } => {
+                write!(f, "line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> SnapshotError {
+        SnapshotError::Io(err)
+    }
+}
+
+impl<I, O> Computer<I, O>
+where
+    I: Input,
+    O: Output,
+{
+    /// Captures the machine's state, including any buffered input and output.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut pending_input: Vec<Base> = self.inbox.iter().copied().collect();
+        pending_input.extend(self.input.buffered());
+
+        Snapshot {
+            name: self.name.clone(),
+            pc: self.pc,
+            rb: self.rb,
+            halted: self.halted,
+            limit: self.mem().limit(),
+            mem: self.mem().to_vec(),
+            pending_input,
+            pending_output: self.output.buffered(),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Rebuilds a machine from the snapshot. Pending input is queued ahead of
+    /// anything `input` provides and pending output is written to `output`.
+    pub fn restore<I, O>(self, input: I, mut output: O) -> Result<Computer<I, O>, Closed>
+    where
+        I: Input,
+        O: Output,
+    {
+        for value in self.pending_output {
+            output.write(value)?;
+        }
+
+        let mut cmp = Computer::new(&self.name, Vec::new(), input, output);
+        *cmp.mem_mut() = Memory::with_limit(self.mem, self.limit);
+        cmp.pc = self.pc;
+        cmp.rb = self.rb;
+        cmp.halted = self.halted;
+        cmp.inbox = VecDeque::from(self.pending_input);
+        Ok(cmp)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        self.check_name()?;
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, SnapshotError> {
+        Snapshot::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the snapshot in the on-disk format. Fails without writing
+    /// anything if the name contains a control character, as it couldn't be
+    /// read back.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.check_name()?;
+        writeln!(w, "{} {}", MAGIC, FORMAT_VERSION)?;
+        writeln!(w, "name {}", self.name)?;
+        writeln!(w, "pc {}", self.pc)?;
+        writeln!(w, "rb {}", self.rb)?;
+        writeln!(w, "halted {}", self.halted as u8)?;
+        writeln!(w, "limit {}", self.limit)?;
+        writeln!(w, "input {}", join(&self.pending_input))?;
+        writeln!(w, "output {}", join(&self.pending_output))?;
+        writeln!(w, "mem {}", join(&self.mem))?;
+        Ok(())
+    }
+
+    fn check_name(&self) -> io::Result<()> {
+        if self.name.chars().any(char::is_control) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("machine name {:?} contains a control character", self.name),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(r: R) -> Result<Snapshot, SnapshotError> {
+        let mut lines = r.lines();
+
+        let header = lines.next().ok_or(SnapshotError::BadHeader)??;
+        let mut parts = header.splitn(2, ' ');
+        if parts.next() != Some(MAGIC) {
+            return Err(SnapshotError::BadHeader);
+        }
+        let version: u32 = parts
+            .next()
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or(SnapshotError::BadHeader)?;
+        if version != FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut name = None;
+        let mut pc = None;
+        let mut rb = None;
+        let mut halted = None;
+        let mut limit = None;
+        let mut pending_input = None;
+        let mut pending_output = None;
+        let mut mem = None;
+
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let line_no = i + 2;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(idx) => (&line[..idx], &line[idx + 1..]),
+                None => (line.as_str(), ""),
+            };
+            let invalid = |message: String| SnapshotError::InvalidField {
+                line: line_no,
+                message,
+            };
+            match key {
+                "name" => name = Some(value.to_owned()),
+                "pc" => pc = Some(parse_value(value).map_err(invalid)?),
+                "rb" => rb = Some(parse_value(value).map_err(invalid)?),
+                "halted" => match value {
+                    "0" => halted = Some(false),
+                    "1" => halted = Some(true),
+                    _ => return Err(invalid(format!("invalid halted flag `{}`", value))),
+                },
+                "limit" => limit = Some(parse_value(value).map_err(invalid)?),
+                "input" => pending_input = Some(parse_list(value).map_err(invalid)?),
+                "output" => pending_output = Some(parse_list(value).map_err(invalid)?),
+                "mem" => mem = Some(parse_list(value).map_err(invalid)?),
+                _ => return Err(invalid(format!("unknown field `{}`", key))),
+            }
+        }
+
+        Ok(Snapshot {
+            name: name.ok_or(SnapshotError::MissingField("name"))?,
+            pc: pc.ok_or(SnapshotError::MissingField("pc"))?,
+            rb: rb.ok_or(SnapshotError::MissingField("rb"))?,
+            halted: halted.ok_or(SnapshotError::MissingField("halted"))?,
+            limit: limit.ok_or(SnapshotError::MissingField("limit"))?,
+            mem: mem.ok_or(SnapshotError::MissingField("mem"))?,
+            pending_input: pending_input.ok_or(SnapshotError::MissingField("input"))?,
+            pending_output: pending_output.ok_or(SnapshotError::MissingField("output"))?,
+        })
+    }
+}
+
+fn join(values: &[Base]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid number `{}`", value))
+}
+
+fn parse_list(value: &str) -> Result<Vec<Base>, String> {
+    if value.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    value.split(',').map(parse_value).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::RunStatus;
+
+    // Reads two values and outputs their sum, then their product.
+    const PROGRAM: [Base; 19] = [
+        3, 17, 3, 18, 1, 17, 18, 19, 4, 19, 2, 17, 18, 19, 4, 19, 99, 0, 0,
+    ];
+
+    fn round_trip(snap: &Snapshot) -> Snapshot {
+        let mut buf = Vec::new();
+        snap.write_to(&mut buf).unwrap();
+        Snapshot::read_from(&buf[..]).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut cmp = Computer::new("Amp A", PROGRAM.to_vec(), VecDeque::new(), Vec::new());
+        cmp.mem_mut().set_limit(4096);
+        cmp.push_input(6);
+        assert_eq!(cmp.run(), Ok(RunStatus::NeedsInput));
+
+        let snap = cmp.snapshot();
+        assert_eq!(round_trip(&snap), snap);
+        assert_eq!(snap.pc, 2);
+        assert_eq!(snap.limit, 4096);
+    }
+
+    #[test]
+    fn test_name_round_trip() {
+        let mut snap =
+            Computer::new("dir/amp A.txt", vec![99], VecDeque::new(), Vec::new()).snapshot();
+        assert_eq!(round_trip(&snap), snap);
+
+        snap.name = "A\npc 7".to_owned();
+        let mut buf = Vec::new();
+        let err = snap.write_to(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_restore_resumes() {
+        let mut cmp = Computer::new("A", PROGRAM.to_vec(), VecDeque::new(), Vec::new());
+        cmp.push_input(6);
+        cmp.input_mut().push_back(7);
+        assert_eq!(cmp.run(), Ok(RunStatus::ProducedOutput(13)));
+
+        let snap = round_trip(&cmp.snapshot());
+        assert_eq!(snap.pending_output, &[13]);
+
+        let mut restored = snap.restore(VecDeque::new(), Vec::new()).unwrap();
+        cmp.compute().unwrap();
+        restored.compute().unwrap();
+
+        assert_eq!(restored.output(), cmp.output());
+        assert_eq!(restored.output(), &[13, 42]);
+        assert_eq!(restored.mem(), cmp.mem());
+        assert!(restored.halted);
+    }
+
+    #[test]
+    fn test_pending_input_survives() {
+        let mut cmp = Computer::new("A", PROGRAM.to_vec(), VecDeque::new(), Vec::new());
+        cmp.push_input(2);
+        cmp.input_mut().push_back(3);
+        let snap = round_trip(&cmp.snapshot());
+        assert_eq!(snap.pending_input, &[2, 3]);
+
+        let mut restored = snap.restore(VecDeque::new(), Vec::new()).unwrap();
+        restored.compute().unwrap();
+        assert_eq!(restored.output(), &[5, 6]);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("intcode-snapshot-{}", std::process::id()));
+        let cmp = Computer::new("A", PROGRAM.to_vec(), VecDeque::new(), Vec::new());
+        let snap = cmp.snapshot();
+        snap.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, snap);
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let text = "INTCODE-SNAPSHOT 2\nname A\n";
+        match Snapshot::read_from(text.as_bytes()) {
+            Err(SnapshotError::UnsupportedVersion(2)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reports_bad_line() {
+        let text = "INTCODE-SNAPSHOT 1\nname A\npc x\n";
+        match Snapshot::read_from(text.as_bytes()) {
+            Err(SnapshotError::InvalidField { line: 3, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+; The standard macro library, available to every assembled program.
+;
+; The stack macros use `rb` as the stack pointer. It holds the address of
+; the next free slot and the stack grows upwards. Point it at spare memory
+; past the program once, at the start:
+;
+;           stack_init #stack
+;           ...
+;   stack:  ZERO 64
+;
+; `call` pushes the return address, so inside a function the last argument
+; pushed by the caller is at `rb-2`, the one before it at `rb-3`, and so on.
+
+; Copies src to dst.
+MACRO mov src, dst
+        ADD %src, #0, %dst
+ENDM
+
+MACRO inc x
+        ADD %x, #1, %x
+ENDM
+
+MACRO dec x
+        ADD %x, #-1, %x
+ENDM
+
+MACRO jmp target
+        JT #1, %target
+ENDM
+
+; Jumps if x is zero.
+MACRO jz x, target
+        JF %x, %target
+ENDM
+
+; Jumps if x is not zero.
+MACRO jnz x, target
+        JT %x, %target
+ENDM
+
+; Decrements x and jumps back to target until it reaches zero.
+MACRO djnz x, target
+        ADD %x, #-1, %x
+        JT %x, %target
+ENDM
+
+; Sets the stack pointer. Only valid while `rb` is still zero.
+MACRO stack_init base
+        ARB %base
+ENDM
+
+MACRO push x
+        ADD %x, #0, rb+0
+        ARB #1
+ENDM
+
+MACRO pop dst
+        ARB #-1
+        ADD rb+0, #0, %dst
+ENDM
+
+; Pushes the return address and jumps to target.
+MACRO call target
+        ADD #.ret, #0, rb+0
+        ARB #1
+        JT #1, %target
+.ret:
+ENDM
+
+; Pops the return address and jumps to it.
+MACRO ret
+        ARB #-1
+        JT #1, rb+0
+ENDM
+
+; Outputs the zero-terminated string at label s, one character at a time.
+MACRO print s
+        ADD #%s, #0, [.test+1]
+        ADD #%s, #0, [.out+1]
+.test:  JF [0], #.done
+.out:   OUT [0]
+        ADD [.test+1], #1, [.test+1]
+        ADD [.out+1], #1, [.out+1]
+        JT #1, #.test
+.done:
+ENDM
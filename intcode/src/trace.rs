@@ -0,0 +1,858 @@
+use crate::disasm::{mnemonic, p_modes};
+use crate::instruction::{parse_instruction, OpCode, PMode};
+use crate::memory::Memory;
+use crate::Base;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const MAGIC: &[u8; 4] = b"ICTR";
+const BINARY_VERSION: u8 = 1;
+
+/// A memory cell changed by an instruction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MemWrite {
+    pub addr: usize,
+    pub old: Base,
+    pub new: Base,
+}
+
+/// One executed instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceRecord {
+    /// The number of instructions the machine had executed before this one.
+    pub step: u64,
+    pub pc: usize,
+    /// The relative base before the instruction ran.
+    pub rb: Base,
+    /// The raw instruction word, from which `op` was decoded.
+    pub word: Base,
+    pub op: OpCode,
+    /// Each parameter resolved: the value read for inputs, or the address
+    /// written for the destination.
+    pub operands: Vec<Base>,
+    pub writes: Vec<MemWrite>,
+    pub input: Option<Base>,
+    pub output: Option<Base>,
+}
+
+/// Receives a record for every instruction a `Computer` executes.
+pub trait Tracer {
+    fn record(&mut self, record: &TraceRecord);
+
+    /// Flushes buffered records and reports the first error seen while
+    /// writing them.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects records in memory. Keep a clone to read them back.
+impl Tracer for Arc<Mutex<Vec<TraceRecord>>> {
+    fn record(&mut self, record: &TraceRecord) {
+        self.lock().unwrap().push(record.clone());
+    }
+}
+
+/// The on-disk trace formats.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TraceFormat {
+    /// One JSON object per line.
+    Json,
+    /// Variable-length integers after a short header. Much smaller than
+    /// JSON for long runs.
+    Binary,
+}
+
+impl TraceFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TraceFormat::Json => "jsonl",
+            TraceFormat::Binary => "trace",
+        }
+    }
+}
+
+impl std::str::FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TraceFormat, String> {
+        match s {
+            "json" | "jsonl" => Ok(TraceFormat::Json),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!("unknown trace format `{}`", s)),
+        }
+    }
+}
+
+/// The path `trace_file` writes the trace of machine `name` to. Letters,
+/// digits and `-` are kept, and every other byte is written as `_` and two
+/// hex digits, so different names always get different files.
+pub fn trace_path(dir: &Path, name: &str, format: TraceFormat) -> PathBuf {
+    let mut stem = String::new();
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' {
+            stem.push(b as char);
+        } else {
+            stem.push_str(&format!("_{:02x}", b));
+        }
+    }
+    dir.join(format!("{}.{}", stem, format.extension()))
+}
+
+/// Creates a tracer writing to a file in `dir` named after the machine.
+pub fn trace_file(
+    dir: &Path,
+    name: &str,
+    format: TraceFormat,
+) -> io::Result<Box<dyn Tracer + Send>> {
+    let w = BufWriter::new(File::create(trace_path(dir, name, format))?);
+    Ok(match format {
+        TraceFormat::Json => Box::new(JsonTracer::new(w, name)),
+        TraceFormat::Binary => Box::new(BinaryTracer::new(w, name)),
+    })
+}
+
+/// Writes each record as a line of JSON, for example:
+///
+/// ```text
+/// {"machine":"A","step":0,"pc":0,"rb":0,"word":1002,"op":"MUL","modes":"PIP",
+///  "operands":[33,3,4],"writes":[{"addr":4,"old":33,"new":99}],"input":null,"output":null}
+/// ```
+///
+/// (shown wrapped; each record is on a single line).
+pub struct JsonTracer<W: Write> {
+    w: W,
+    name: String,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(w: W, name: &str) -> JsonTracer<W> {
+        JsonTracer {
+            w,
+            name: name.to_owned(),
+            error: None,
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn record(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        let writes: Vec<String> = record
+            .writes
+            .iter()
+            .map(|w| {
+                format!(
+                    "{{\"addr\":{},\"old\":{},\"new\":{}}}",
+                    w.addr, w.old, w.new
+                )
+            })
+            .collect();
+        let operands: Vec<String> = record.operands.iter().map(|v| v.to_string()).collect();
+        let modes: String = p_modes(&record.op).iter().map(|m| mode_char(*m)).collect();
+        let result = writeln!(
+            self.w,
+            "{{\"machine\":{},\"step\":{},\"pc\":{},\"rb\":{},\"word\":{},\"op\":\"{}\",\"modes\":\"{}\",\"operands\":[{}],\"writes\":[{}],\"input\":{},\"output\":{}}}",
+            json_string(&self.name),
+            record.step,
+            record.pc,
+            record.rb,
+            record.word,
+            mnemonic(&record.op),
+            modes,
+            operands.join(","),
+            writes.join(","),
+            json_option(record.input),
+            json_option(record.output),
+        );
+        self.error = result.err();
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.w.flush(),
+        }
+    }
+}
+
+/// Writes records in the compact binary format: the bytes `ICTR`, a version
+/// byte and the machine name, then per record the step, pc, rb, word,
+/// operands, writes and an I/O flags byte followed by any input and output
+/// value. Integers are LEB128 varints, zigzag-encoded where they may be
+/// negative.
+pub struct BinaryTracer<W: Write> {
+    w: W,
+    name: String,
+    started: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> BinaryTracer<W> {
+    pub fn new(w: W, name: &str) -> BinaryTracer<W> {
+        BinaryTracer {
+            w,
+            name: name.to_owned(),
+            started: false,
+            error: None,
+        }
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        let mut buf = MAGIC.to_vec();
+        buf.push(BINARY_VERSION);
+        put_uint(&mut buf, self.name.len() as u64);
+        buf.extend(self.name.as_bytes());
+        self.w.write_all(&buf)
+    }
+}
+
+impl<W: Write> Tracer for BinaryTracer<W> {
+    fn record(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            if let Err(err) = self.header() {
+                self.error = Some(err);
+                return;
+            }
+        }
+
+        let mut buf = Vec::new();
+        put_uint(&mut buf, record.step);
+        put_uint(&mut buf, record.pc as u64);
+        put_int(&mut buf, record.rb);
+        put_int(&mut buf, record.word);
+        put_uint(&mut buf, record.operands.len() as u64);
+        for &v in &record.operands {
+            put_int(&mut buf, v);
+        }
+        put_uint(&mut buf, record.writes.len() as u64);
+        for w in &record.writes {
+            put_uint(&mut buf, w.addr as u64);
+            put_int(&mut buf, w.old);
+            put_int(&mut buf, w.new);
+        }
+        buf.push(record.input.is_some() as u8 | (record.output.is_some() as u8) << 1);
+        for v in record.input.iter().chain(record.output.iter()) {
+            put_int(&mut buf, *v);
+        }
+        self.error = self.w.write_all(&buf).err();
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.started && self.error.is_none() {
+            self.started = true;
+            self.header()?;
+        }
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.w.flush(),
+        }
+    }
+}
+
+/// A trace read back from a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    /// The machine's name. Empty for a JSON trace with no records.
+    pub name: String,
+    pub records: Vec<TraceRecord>,
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    /// `record` counts from 1; for JSON traces it is the line number.
+    Invalid {
+        record: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Io(err) => write!(f, "{}", err),
+            TraceError::Invalid { record, message } => write!(f, "record {}: {}", record, message),
+        }
+    }
+}
+
+impl Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(err: io::Error) -> TraceError {
+        TraceError::Io(err)
+    }
+}
+
+impl Trace {
+    /// Reads a trace in either format, telling them apart by the header.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Trace, TraceError> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        Trace::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Trace, TraceError> {
+        if bytes.starts_with(MAGIC) {
+            parse_binary(bytes)
+        } else {
+            let text = std::str::from_utf8(bytes).map_err(|_| TraceError::Invalid {
+                record: 1,
+                message: "not a trace".to_owned(),
+            })?;
+            parse_json(text)
+        }
+    }
+}
+
+/// Resolves the parameters of `op` at `pc` the way `TraceRecord::operands`
+/// describes them.
+pub(crate) fn resolve_operands(mem: &Memory, rb: Base, op: &OpCode, pc: usize) -> Vec<Base> {
+    let write = write_param(op);
+    p_modes(op)
+        .iter()
+        .enumerate()
+        .map(|(i, p_mode)| {
+            let param = mem.get(pc + 1 + i).unwrap_or(0);
+            let addr = match p_mode {
+                PMode::Immediate => return param,
+                PMode::Position => param,
+                PMode::Relative => match rb.checked_add(param) {
+                    Some(addr) => addr,
+                    None => return 0,
+                },
+            };
+            if write == Some(i) || addr < 0 {
+                addr
+            } else {
+                mem.get(addr as usize).unwrap_or(0)
+            }
+        })
+        .collect()
+}
+
+/// The index of the parameter `op` writes to, if any.
+pub(crate) fn write_param(op: &OpCode) -> Option<usize> {
+    match op {
+        OpCode::Add { .. } | OpCode::Multiply { .. } | OpCode::Less { .. } | OpCode::Eq { .. } => {
+            Some(2)
+        }
+        OpCode::Input { .. } => Some(0),
+        _ => None,
+    }
+}
+
+fn mode_char(p_mode: PMode) -> char {
+    match p_mode {
+        PMode::Position => 'P',
+        PMode::Immediate => 'I',
+        PMode::Relative => 'R',
+    }
+}
+
+fn json_option(value: Option<Base>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => "null".to_owned(),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn put_uint(buf: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn put_int(buf: &mut Vec<u8>, v: Base) {
+    put_uint(buf, ((v << 1) ^ (v >> 63)) as u64);
+}
+
+struct Bytes<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let b = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| "unexpected end of trace".to_owned())?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn uint(&mut self) -> Result<u64, String> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            v |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err("varint too long".to_owned())
+    }
+
+    fn int(&mut self) -> Result<Base, String> {
+        let v = self.uint()?;
+        Ok((v >> 1) as Base ^ -((v & 1) as Base))
+    }
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Trace, TraceError> {
+    let mut r = Bytes { bytes, pos: 0 };
+    let invalid = |record: usize| move |message: String| TraceError::Invalid { record, message };
+
+    r.pos = MAGIC.len();
+    let version = r.byte().map_err(invalid(0))?;
+    if version != BINARY_VERSION {
+        return Err(invalid(0)(format!("unsupported trace version {}", version)));
+    }
+    let len = r.uint().map_err(invalid(0))? as usize;
+    let name = r
+        .pos
+        .checked_add(len)
+        .and_then(|end| bytes.get(r.pos..end))
+        .and_then(|name| std::str::from_utf8(name).ok())
+        .ok_or_else(|| invalid(0)("invalid machine name".to_owned()))?
+        .to_owned();
+    r.pos += len;
+
+    let mut records = Vec::new();
+    while r.pos < bytes.len() {
+        let record = records.len() + 1;
+        records.push(read_record(&mut r).map_err(invalid(record))?);
+    }
+    Ok(Trace { name, records })
+}
+
+fn read_record(r: &mut Bytes) -> Result<TraceRecord, String> {
+    let step = r.uint()?;
+    let pc = r.uint()? as usize;
+    let rb = r.int()?;
+    let word = r.int()?;
+    let op = parse_instruction(word).map_err(|_| format!("invalid instruction {}", word))?;
+    let operands = (0..r.uint()?)
+        .map(|_| r.int())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut writes = Vec::new();
+    for _ in 0..r.uint()? {
+        writes.push(MemWrite {
+            addr: r.uint()? as usize,
+            old: r.int()?,
+            new: r.int()?,
+        });
+    }
+    let flags = r.byte()?;
+    let input = if flags & 1 != 0 { Some(r.int()?) } else { None };
+    let output = if flags & 2 != 0 { Some(r.int()?) } else { None };
+    Ok(TraceRecord {
+        step,
+        pc,
+        rb,
+        word,
+        op,
+        operands,
+        writes,
+        input,
+        output,
+    })
+}
+
+fn parse_json(text: &str) -> Result<Trace, TraceError> {
+    let mut name = String::new();
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |message: String| TraceError::Invalid {
+            record: i + 1,
+            message,
+        };
+        let value = Json::parse(line).map_err(invalid)?;
+        if records.is_empty() {
+            name = value
+                .field("machine")
+                .and_then(Json::as_str)
+                .unwrap_or("")
+                .to_owned();
+        }
+        records.push(json_record(&value).map_err(invalid)?);
+    }
+    Ok(Trace { name, records })
+}
+
+fn json_record(value: &Json) -> Result<TraceRecord, String> {
+    let int = |key: &str| {
+        value
+            .field(key)
+            .and_then(Json::as_int)
+            .ok_or_else(|| format!("missing or invalid `{}`", key))
+    };
+    let opt = |key: &str| match value.field(key) {
+        Some(Json::Null) | None => Ok(None),
+        Some(v) => v
+            .as_int()
+            .map(Some)
+            .ok_or_else(|| format!("invalid `{}`", key)),
+    };
+    let list = |key: &str| match value.field(key) {
+        Some(Json::Array(items)) => Ok(items),
+        _ => Err(format!("missing or invalid `{}`", key)),
+    };
+
+    let word = int("word")?;
+    let op = parse_instruction(word).map_err(|_| format!("invalid instruction {}", word))?;
+    let operands = list("operands")?
+        .iter()
+        .map(|v| v.as_int().ok_or_else(|| "invalid operand".to_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let writes = list("writes")?
+        .iter()
+        .map(|w| {
+            let get = |key| w.field(key).and_then(Json::as_int);
+            match (get("addr"), get("old"), get("new")) {
+                (Some(addr), Some(old), Some(new)) if addr >= 0 => Ok(MemWrite {
+                    addr: addr as usize,
+                    old,
+                    new,
+                }),
+                _ => Err("invalid write".to_owned()),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(TraceRecord {
+        step: int("step")? as u64,
+        pc: int("pc")? as usize,
+        rb: int("rb")?,
+        word,
+        op,
+        operands,
+        writes,
+        input: opt("input")?,
+        output: opt("output")?,
+    })
+}
+
+/// Just enough JSON to read traces back.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Int(Base),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut chars = text.trim().chars().peekable();
+        let value = Json::value(&mut chars)?;
+        if chars.next().is_some() {
+            return Err("trailing characters".to_owned());
+        }
+        Ok(value)
+    }
+
+    fn value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Json, String> {
+        skip_ws(chars);
+        match chars.peek().copied() {
+            Some('n') => {
+                let word: String = chars.take(4).collect();
+                if word == "null" {
+                    Ok(Json::Null)
+                } else {
+                    Err(format!("unexpected `{}`", word))
+                }
+            }
+            Some('"') => Ok(Json::Str(json_str(chars)?)),
+            Some('[') => {
+                chars.next();
+                let mut items = Vec::new();
+                skip_ws(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(Json::value(chars)?);
+                    skip_ws(chars);
+                    match chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return Err("expected `,` or `]`".to_owned()),
+                    }
+                }
+            }
+            Some('{') => {
+                chars.next();
+                let mut fields = Vec::new();
+                skip_ws(chars);
+                if chars.peek() == Some(&'}') {
+                    chars.next();
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    skip_ws(chars);
+                    let key = json_str(chars)?;
+                    skip_ws(chars);
+                    if chars.next() != Some(':') {
+                        return Err("expected `:`".to_owned());
+                    }
+                    fields.push((key, Json::value(chars)?));
+                    skip_ws(chars);
+                    match chars.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return Err("expected `,` or `}`".to_owned()),
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut num = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c != '-' && !c.is_ascii_digit() {
+                        break;
+                    }
+                    num.push(c);
+                    chars.next();
+                }
+                num.parse()
+                    .map(Json::Int)
+                    .map_err(|_| format!("invalid number `{}`", num))
+            }
+            _ => Err("expected a value".to_owned()),
+        }
+    }
+
+    fn field(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<Base> {
+        match self {
+            Json::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+fn skip_ws(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn json_str(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("expected a string".to_owned());
+    }
+    let mut out = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(out),
+            Some('\\') => match chars.next() {
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('/') => out.push('/'),
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('u') => {
+                    let hex: String = chars.take(4).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                        .ok_or_else(|| format!("invalid escape `\\u{}`", hex))?;
+                    out.push(c);
+                }
+                _ => return Err("invalid escape".to_owned()),
+            },
+            Some(c) => out.push(c),
+            None => return Err("unterminated string".to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Computer;
+    use std::collections::VecDeque;
+
+    // Reads a value, doubles it in place and outputs it.
+    const DOUBLER: [Base; 9] = [3, 9, 1002, 9, 2, 9, 4, 9, 99];
+
+    fn traced<T: Tracer + Clone + Send + 'static>(tracer: T) -> T {
+        let mut cmp = Computer::new(
+            "Amp \"A\"",
+            DOUBLER.to_vec(),
+            VecDeque::from(vec![21]),
+            Vec::new(),
+        );
+        cmp.set_tracer(Box::new(tracer.clone()));
+        cmp.compute().unwrap();
+        cmp.take_tracer().unwrap().finish().unwrap();
+        tracer
+    }
+
+    fn records() -> Vec<TraceRecord> {
+        let shared = traced(Arc::new(Mutex::new(Vec::new())));
+        let records = shared.lock().unwrap().clone();
+        records
+    }
+
+    #[derive(Clone)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        let records = records();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].input, Some(21));
+        assert_eq!(
+            records[0].writes,
+            &[MemWrite {
+                addr: 9,
+                old: 0,
+                new: 21
+            }]
+        );
+        assert_eq!(records[1].step, 1);
+        assert_eq!(records[1].pc, 2);
+        assert_eq!(records[1].operands, &[21, 2, 9]);
+        assert_eq!(records[2].output, Some(42));
+        assert_eq!(records[3].op, OpCode::Halt);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let buf = SharedBuf(Arc::new(Mutex::new(Vec::new())));
+        let mut tracer = JsonTracer::new(buf.clone(), "Amp \"A\"");
+        for record in &records() {
+            tracer.record(record);
+        }
+        tracer.finish().unwrap();
+
+        let bytes = buf.0.lock().unwrap().clone();
+        let first = String::from_utf8(bytes.clone()).unwrap();
+        assert!(first.starts_with(
+            "{\"machine\":\"Amp \\\"A\\\"\",\"step\":0,\"pc\":0,\"rb\":0,\"word\":3,\"op\":\"IN\",\"modes\":\"P\""
+        ));
+        let trace = Trace::parse(&bytes).unwrap();
+        assert_eq!(trace.name, "Amp \"A\"");
+        assert_eq!(trace.records, records());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let buf = SharedBuf(Arc::new(Mutex::new(Vec::new())));
+        let mut tracer = BinaryTracer::new(buf.clone(), "A");
+        let mut records = records();
+        records[1].rb = -70000;
+        for record in &records {
+            tracer.record(record);
+        }
+        tracer.finish().unwrap();
+
+        let trace = Trace::parse(&buf.0.lock().unwrap()).unwrap();
+        assert_eq!(trace.name, "A");
+        assert_eq!(trace.records, records);
+    }
+
+    #[test]
+    fn test_binary_corrupt_name() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(BINARY_VERSION);
+        put_uint(&mut bytes, u64::MAX);
+        bytes.extend_from_slice(b"A");
+        assert_eq!(
+            Trace::parse(&bytes).unwrap_err().to_string(),
+            TraceError::Invalid {
+                record: 0,
+                message: "invalid machine name".to_owned()
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_trace_path_unique() {
+        let dir = Path::new("out");
+        let path = |name| trace_path(dir, name, TraceFormat::Json);
+        assert_eq!(path("amp-A1"), dir.join("amp-A1.jsonl"));
+        assert_eq!(path("a.b"), dir.join("a_2eb.jsonl"));
+        assert_eq!(path("a_b"), dir.join("a_5fb.jsonl"));
+        assert_ne!(path("a/b"), path("a_2fb"));
+    }
+
+    #[test]
+    fn test_trace_file() {
+        let dir = std::env::temp_dir();
+        let name = format!("trace test {}", std::process::id());
+        let path = trace_path(&dir, &name, TraceFormat::Binary);
+        assert!(path.ends_with(format!("trace_20test_20{}.trace", std::process::id())));
+
+        let mut cmp = Computer::new(&name, DOUBLER.to_vec(), VecDeque::from(vec![1]), Vec::new());
+        cmp.set_tracer(trace_file(&dir, &name, TraceFormat::Binary).unwrap());
+        cmp.compute().unwrap();
+        cmp.take_tracer().unwrap().finish().unwrap();
+
+        let trace = Trace::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(trace.name, name);
+        assert_eq!(trace.records.len(), 4);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::disasm::{mnemonic, p_modes};
+use crate::instruction::PMode;
+use crate::trace::{write_param, Trace, TraceRecord};
+use std::fmt::Write;
+
+/// The first record at which two traces differ.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The index of the record in both traces.
+    pub index: usize,
+    /// The fields that differ, e.g. `pc` or `writes`. `end of trace` means
+    /// one trace stopped while the other went on.
+    pub fields: Vec<&'static str>,
+}
+
+/// Compares two runs record by record and returns where they first differ,
+/// or `None` if they executed the same instructions with the same effects.
+/// Machine names are not compared.
+pub fn first_divergence(a: &[TraceRecord], b: &[TraceRecord]) -> Option<Divergence> {
+    for (index, (x, y)) in a.iter().zip(b).enumerate() {
+        let fields = differences(x, y);
+        if !fields.is_empty() {
+            return Some(Divergence { index, fields });
+        }
+    }
+    if a.len() != b.len() {
+        return Some(Divergence {
+            index: a.len().min(b.len()),
+            fields: vec!["end of trace"],
+        });
+    }
+    None
+}
+
+fn differences(a: &TraceRecord, b: &TraceRecord) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if a.step != b.step {
+        fields.push("step");
+    }
+    if a.pc != b.pc {
+        fields.push("pc");
+    }
+    if a.rb != b.rb {
+        fields.push("rb");
+    }
+    if a.word != b.word {
+        fields.push("instruction");
+    }
+    if a.operands != b.operands {
+        fields.push("operands");
+    }
+    if a.writes != b.writes {
+        fields.push("writes");
+    }
+    if a.input != b.input {
+        fields.push("input");
+    }
+    if a.output != b.output {
+        fields.push("output");
+    }
+    fields
+}
+
+/// Formats a record on one line, e.g.
+/// `step 2  pc 4  rb 0  MUL 1002  =-4321 #3 ->[24]  write [24]: -4321 -> -12963`.
+///
+/// Operands show the mode they were read with and the value they resolved
+/// to; the destination shows the address written.
+pub fn format_record(record: &TraceRecord) -> String {
+    let mut text = format!(
+        "step {}  pc {}  rb {}  {} {}",
+        record.step,
+        record.pc,
+        record.rb,
+        mnemonic(&record.op),
+        record.word
+    );
+    let dest = write_param(&record.op);
+    for (i, (&p_mode, &value)) in p_modes(&record.op).iter().zip(&record.operands).enumerate() {
+        let operand = match p_mode {
+            _ if dest == Some(i) => format!("->[{}]", value),
+            PMode::Immediate => format!("#{}", value),
+            PMode::Position | PMode::Relative => format!("={}", value),
+        };
+        text.push_str(if i == 0 { "  " } else { " " });
+        text.push_str(&operand);
+    }
+    for w in &record.writes {
+        write!(text, "  write [{}]: {} -> {}", w.addr, w.old, w.new).unwrap();
+    }
+    if let Some(value) = record.input {
+        write!(text, "  input {}", value).unwrap();
+    }
+    if let Some(value) = record.output {
+        write!(text, "  output {}", value).unwrap();
+    }
+    text
+}
+
+/// Describes a divergence diff-style: up to `context` matching records
+/// before it, then the differing records and up to `context` records after
+/// from each side, `<` marking `a` and `>` marking `b`.
+pub fn divergence_report(a: &Trace, b: &Trace, divergence: &Divergence, context: usize) -> String {
+    let index = divergence.index;
+    let mut report = format!(
+        "traces diverge at record {}: {}\n",
+        index + 1,
+        divergence.fields.join(", ")
+    );
+    for record in &a.records[index.saturating_sub(context)..index] {
+        writeln!(report, "  {}", format_record(record)).unwrap();
+    }
+    for (marker, trace) in [("<", a), (">", b)].iter() {
+        let after = trace.records.iter().skip(index).take(context + 1);
+        let mut empty = true;
+        for record in after {
+            writeln!(report, "{} {}", marker, format_record(record)).unwrap();
+            empty = false;
+        }
+        if empty {
+            writeln!(report, "{} (end of trace)", marker).unwrap();
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Computer;
+    use crate::Base;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    // Outputs its input doubled. A zero input is output first.
+    const PROGRAM: [Base; 15] = [3, 14, 1005, 14, 7, 4, 14, 1002, 14, 2, 14, 4, 14, 99, 0];
+
+    fn trace(input: Base) -> Trace {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let mut cmp = Computer::new(
+            "t",
+            PROGRAM.to_vec(),
+            VecDeque::from(vec![input]),
+            Vec::new(),
+        );
+        cmp.set_tracer(Box::new(records.clone()));
+        cmp.compute().unwrap();
+        let records = records.lock().unwrap().clone();
+        Trace {
+            name: "t".to_owned(),
+            records,
+        }
+    }
+
+    #[test]
+    fn test_identical() {
+        assert_eq!(first_divergence(&trace(4).records, &trace(4).records), None);
+    }
+
+    #[test]
+    fn test_divergence() {
+        let (a, b) = (trace(4), trace(5));
+        let divergence = first_divergence(&a.records, &b.records).unwrap();
+        assert_eq!(
+            divergence,
+            Divergence {
+                index: 0,
+                fields: vec!["writes", "input"],
+            }
+        );
+
+        let mut b = trace(4);
+        b.records[1].operands[0] = 7;
+        let divergence = first_divergence(&a.records, &b.records).unwrap();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(divergence.fields, &["operands"]);
+        let report = divergence_report(&a, &b, &divergence, 1);
+        assert_eq!(
+            report.lines().collect::<Vec<_>>(),
+            &[
+                "traces diverge at record 2: operands",
+                "  step 0  pc 0  rb 0  IN 3  ->[14]  write [14]: 0 -> 4  input 4",
+                "< step 1  pc 2  rb 0  JT 1005  =4 #7",
+                "< step 2  pc 7  rb 0  MUL 1002  =4 #2 ->[14]  write [14]: 4 -> 8",
+                "> step 1  pc 2  rb 0  JT 1005  =7 #7",
+                "> step 2  pc 7  rb 0  MUL 1002  =4 #2 ->[14]  write [14]: 4 -> 8",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_end_of_trace() {
+        let a = trace(4);
+        let mut b = a.clone();
+        b.records.truncate(2);
+        let divergence = first_divergence(&a.records, &b.records).unwrap();
+        assert_eq!(divergence.index, 2);
+        assert_eq!(divergence.fields, &["end of trace"]);
+        let report = divergence_report(&a, &b, &divergence, 0);
+        assert!(report.ends_with("> (end of trace)\n"));
+    }
+}
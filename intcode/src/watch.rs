@@ -0,0 +1,116 @@
+use crate::instruction::{OpCode, PMode};
+use crate::memory::Memory;
+use crate::Base;
+
+/// A memory access made by a single instruction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MemAccess {
+    /// A position or relative mode operand was read.
+    Read {
+        addr: usize,
+        value: Base,
+    },
+    Write {
+        addr: usize,
+        old: Base,
+        new: Base,
+    },
+}
+
+impl MemAccess {
+    pub fn addr(&self) -> usize {
+        match *self {
+            MemAccess::Read { addr, .. } | MemAccess::Write { addr, .. } => addr,
+        }
+    }
+}
+
+/// A range of cells to watch for reads, writes or both.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: usize,
+    /// The last watched cell, inclusive.
+    pub end: usize,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: &MemAccess) -> bool {
+        let wanted = match access {
+            MemAccess::Read { .. } => self.read,
+            MemAccess::Write { .. } => self.write,
+        };
+        wanted && self.start <= access.addr() && access.addr() <= self.end
+    }
+}
+
+/// The addresses `op` at `pc` reads through position or relative mode
+/// operands. Immediate operands and the instruction's own cells are not
+/// counted.
+pub(crate) fn operand_reads(mem: &Memory, rb: Base, op: &OpCode, pc: usize) -> Vec<usize> {
+    let modes: &[PMode] = match op {
+        OpCode::Add { p_modes }
+        | OpCode::Multiply { p_modes }
+        | OpCode::Less { p_modes }
+        | OpCode::Eq { p_modes } => &p_modes[..2],
+        OpCode::JmpT { p_modes } | OpCode::JmpF { p_modes } => p_modes,
+        OpCode::Output { p_modes } | OpCode::AdjustBase { p_modes } => p_modes,
+        OpCode::Input { .. } | OpCode::Halt => &[],
+    };
+    modes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p_mode)| {
+            let param = mem.get(pc + 1 + i).ok()?;
+            let addr = match p_mode {
+                PMode::Position => param,
+                PMode::Relative => rb.checked_add(param)?,
+                PMode::Immediate => return None,
+            };
+            if addr < 0 {
+                None
+            } else {
+                Some(addr as usize)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::parse_instruction;
+
+    #[test]
+    fn test_operand_reads() {
+        let mem = Memory::new(vec![1201, 5, 6, 7, 99]);
+        let op = parse_instruction(1201).unwrap();
+        assert_eq!(operand_reads(&mem, 0, &op, 0), &[5]);
+
+        let mem = Memory::new(vec![2205, 1, -4]);
+        let op = parse_instruction(2205).unwrap();
+        assert_eq!(operand_reads(&mem, 10, &op, 0), &[11, 6]);
+    }
+
+    #[test]
+    fn test_watchpoint_matches() {
+        let wp = Watchpoint {
+            start: 4,
+            end: 6,
+            read: false,
+            write: true,
+        };
+        assert!(wp.matches(&MemAccess::Write {
+            addr: 6,
+            old: 0,
+            new: 1
+        }));
+        assert!(!wp.matches(&MemAccess::Write {
+            addr: 7,
+            old: 0,
+            new: 1
+        }));
+        assert!(!wp.matches(&MemAccess::Read { addr: 5, value: 0 }));
+    }
+}